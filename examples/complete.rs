@@ -5,8 +5,8 @@ use std::iter::FromIterator;
 use std::{thread, time};
 
 use awesome_bot::{
-    Audio, AwesomeBot, ChatAction, Document, Finisher, Float, Message, MessageType, PhotoSize,
-    ReplyKeyboardMarkup, Sticker, Video, Voice,
+    Audio, AwesomeBot, CallbackQuery, ChatAction, Document, Finisher, Float, KeyboardBuilder,
+    InputFile, Message, MessageType, PhotoSize, Result, Sticker, Video, Voice,
 };
 
 macro_rules! debug {
@@ -24,22 +24,31 @@ macro_rules! debug_msg {
     };
 }
 
-fn transform(vecs: Vec<Vec<&str>>) -> Vec<Vec<String>> {
-    vecs.iter()
-        .map(|x| x.iter().map(|x| x.to_string()).collect())
-        .collect()
-}
-
 fn cmd_keyboard(bot: &AwesomeBot, msg: &Message, _: String) {
-    let kbl = ReplyKeyboardMarkup {
-        keyboard: transform(vec![vec!["I", "<3"], vec!["You"]]),
-        resize_keyboard: None,
-        one_time_keyboard: None,
-        selective: None,
-    };
+    let kbl = KeyboardBuilder::reply()
+        .button("I")
+        .button("<3")
+        .row()
+        .button("You")
+        .build();
     debug!(bot.answer(msg).text("There you go!").keyboard(kbl).end());
 }
 
+fn cmd_inline_keyboard(bot: &AwesomeBot, msg: &Message, _: String) {
+    let kbl = KeyboardBuilder::inline()
+        .callback("Yes", "like:yes")
+        .callback("No", "like:no")
+        .row()
+        .url("Telegram", "https://telegram.org")
+        .build();
+    debug!(bot.answer(msg).text("Do you like it?").keyboard(kbl).end());
+}
+
+fn like_callback(bot: &AwesomeBot, query: &CallbackQuery, _: String, args: Vec<String>) {
+    let text = if args[1] == "yes" { "Yay!" } else { "Oh :(" };
+    debug!(bot.answer_callback(&query.id).text(text).end());
+}
+
 fn test_async_hand(bot: &AwesomeBot, msg: &Message, _: String) {
     debug!(
         bot.answer(msg)
@@ -50,28 +59,29 @@ fn test_async_hand(bot: &AwesomeBot, msg: &Message, _: String) {
     debug!(bot.answer(msg).text("End async test").end());
 }
 
-fn divide_by_two<T: Iterator>(it: T) -> Vec<Vec<T::Item>> {
-    let mut result: Vec<Vec<T::Item>> = vec![];
-    let mut nit = it;
-    loop {
-        let e1 = nit.next();
-        let e2 = nit.next();
-        match (e1, e2) {
-            (None, _) => break, // means that we are done!
-            (Some(e11), Some(e21)) => result.push(vec![e11, e21]),
-            (Some(e11), None) => result.push(vec![e11]),
-        }
-    }
-    result
+fn ask_name_hand(bot: &AwesomeBot, msg: &Message, _: String) {
+    debug!(bot.answer(msg).text("What's your name?").end());
+    // The next message is for this handler, the routes don't receive it
+    let text = match bot.wait_reply(msg, time::Duration::from_secs(30)) {
+        Some(Message {
+            msg: MessageType::Text(name),
+            ..
+        }) => format!("Nice to meet you, {}!", name),
+        Some(_) => "That's not a name...".to_owned(),
+        None => "Too late!".to_owned(),
+    };
+    debug!(bot.answer(msg).text(&text).end());
 }
 
 fn show_me_hand(bot: &AwesomeBot, msg: &Message, _: String) {
     let cmds = HashMap::<&str, &str>::from_iter(vec![
         ("/start", "Start the bot!"),
         ("/keyboard", "Send you a keyboard"),
+        ("/inlinekeyboard", "Send you an inline keyboard"),
         ("/hidekeyboard", "Hide the keyboard"),
         ("/hardecho", "Echo with force reply"),
         ("/forwardme", "Forward that message to you"),
+        ("/askname", "Ask your name and wait for the answer"),
         (
             "/sleep",
             "Sleep for 5 seconds, without blocking, awesome goroutines",
@@ -89,13 +99,11 @@ fn show_me_hand(bot: &AwesomeBot, msg: &Message, _: String) {
         ("/sendchataction", "Sends a random chat action"),
     ]);
 
-    let commands_k = divide_by_two(cmds.into_iter().map(|(x, _)| x));
-    let kbl = ReplyKeyboardMarkup {
-        keyboard: transform(commands_k),
-        resize_keyboard: None,
-        one_time_keyboard: Some(true),
-        selective: None,
-    };
+    let mut kb = KeyboardBuilder::reply();
+    for (cmd, _) in cmds {
+        kb.button(cmd);
+    }
+    let kbl = kb.columns(2).one_time(true).build();
     debug!(
         bot.answer(msg)
             .text("There you have the commands!")
@@ -116,12 +124,19 @@ fn hard_echo(bot: &AwesomeBot, msg: &Message, _: String, args: Vec<String>) {
     debug!(bot.answer(msg).text(&args[1]).force(true).end());
 }
 
-fn hello_hand(bot: &AwesomeBot, msg: &Message, _: String) {
-    debug!(
+fn hello_hand(bot: &AwesomeBot, msg: &Message, _: String) -> Result<()> {
+    // The errors go to the on_error hook
+    try!(
         bot.answer(msg)
             .text(&format!("Hi {}!", msg.from.first_name))
             .end()
     );
+    Ok(())
+}
+
+fn roll_hand(bot: &AwesomeBot, msg: &Message, (dice, sides): (u32, u32)) {
+    let text = format!("Rolling {} dice of {} sides...", dice, sides);
+    debug!(bot.answer(msg).text(&text).end());
 }
 
 fn tell_me_hand(bot: &AwesomeBot, msg: &Message, _: String, args: Vec<String>) {
@@ -140,32 +155,46 @@ fn all_msg_hand(_: &AwesomeBot, msg: &Message) {
     }
 }
 
+// ========
+// FALLBACK
+// ========
+
+fn unhandled_hand(bot: &AwesomeBot, msg: &Message) {
+    let text = match msg.msg {
+        MessageType::Text(ref t) if t.starts_with("/") => {
+            "I don't know that command, try /showmecommands"
+        }
+        _ => "I don't know what to do with that :(",
+    };
+    debug!(bot.answer(msg).text(text).end());
+}
+
 // =============
 // Send media handlers
 // =============
 
 fn handimage(bot: &AwesomeBot, msg: &Message, _: String) {
-    debug!(bot.answer(msg).photo("files/test.jpg").end());
+    debug!(bot.answer(msg).photo(InputFile::path("files/test.jpg")).end());
 }
 
 fn handaudio(bot: &AwesomeBot, msg: &Message, _: String) {
-    debug!(bot.answer(msg).audio("files/test.mp3").end());
+    debug!(bot.answer(msg).audio(InputFile::path("files/test.mp3")).end());
 }
 
 fn handvoice(bot: &AwesomeBot, msg: &Message, _: String) {
-    debug!(bot.answer(msg).voice("files/test.mp3").end());
+    debug!(bot.answer(msg).voice(InputFile::path("files/test.mp3")).end());
 }
 
 fn handdoc(bot: &AwesomeBot, msg: &Message, _: String) {
-    debug!(bot.answer(msg).document("files/test.pdf").end());
+    debug!(bot.answer(msg).document(InputFile::path("files/test.pdf")).end());
 }
 
 fn handstick(bot: &AwesomeBot, msg: &Message, _: String) {
-    debug!(bot.answer(msg).sticker("files/test.webp").end());
+    debug!(bot.answer(msg).sticker(InputFile::path("files/test.webp")).end());
 }
 
 fn handvideo(bot: &AwesomeBot, msg: &Message, _: String) {
-    debug!(bot.answer(msg).video("files/test.mp4").end());
+    debug!(bot.answer(msg).video(InputFile::path("files/test.mp4")).end());
 }
 
 fn handlocation(bot: &AwesomeBot, msg: &Message, _: String) {
@@ -274,14 +303,21 @@ fn main() {
 
     // Logger :)
     bot.any_fn(all_msg_hand); // Just to print all the messages
+    bot.on_error(|_, update, route, e| {
+        println!("Error in {} handling update {}: {}", route, update.update_id, e);
+    });
 
     // Random handlers
     bot.simple_command("sleep", test_async_hand) // Test to prove asynchronous
         .simple_command("showmecommands", show_me_hand) // Send a keyboard with all the commands
         .simple_command("keyboard", cmd_keyboard) // Send a keyboard
+        .simple_command("inlinekeyboard", cmd_inline_keyboard) // Send an inline keyboard
+        .callback("^like:(yes|no)$", like_callback) // Answer the inline keyboard buttons
         .simple_command("hidekeyboard", hide_keyboard) // Hide the keyboard
         .simple_command("forwardme", handforw) // Forward the message
+        .simple_command("askname", ask_name_hand) // Wait for the reply of the user
         .command("hardecho (.+)", hard_echo) // Echo the text with a force reply
+        .typed_command("roll", "/roll <dice> <sides>", roll_hand) // Typed arguments
         .simple_regex("^Hello!?$", hello_hand) // Answer to Hello!
         .regex("^Tell me (.+)$", tell_me_hand); // An echo without command
 
@@ -306,6 +342,9 @@ fn main() {
         .video_fn(video_handler)
         .location_fn(location_handler);
 
+    // Help when nothing matched
+    bot.unhandled_fn(unhandled_hand);
+
     let res = bot.simple_start();
     if let Err(e) = res {
         println!("An error occured: {}", e);
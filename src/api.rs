@@ -0,0 +1,509 @@
+// The Bot API client of the bot. The `Api` of telegram-bot 0.4 only has the methods of
+// its version of the Bot API and can't upload files, retry or wait for the rate limits,
+// so every request goes through this client instead. It's internal, the users only see
+// the builders that use it.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hyper::header::Headers;
+use hyper::Client as HttpClient;
+use rustc_serialize::json::{self, Json, ToJson};
+use rustc_serialize::Decodable;
+use error::{BotError, DownloadError};
+use file::{FileInfo, InputFile};
+use handler::SendErrorHook;
+use queue::{RateLimits, SendQueue};
+use retry::RetryPolicy;
+use shutdown::ShutdownHandle;
+use telegram_bot::{Error, Integer, Result, User};
+use update::{decode_update, Received};
+
+/// The URL of the official Bot API server.
+pub const DEFAULT_API_URL: &'static str = "https://api.telegram.org";
+
+/// Biggest file that can be downloaded by default (the limit of the Bot API), 20 MB.
+pub const DEFAULT_DOWNLOAD_LIMIT: u64 = 20 * 1024 * 1024;
+
+// Times that a call is repeated when Telegram answers that we are sending too fast
+const MAX_RETRY_AFTER: usize = 5;
+
+/// Parameters of a Bot API method call.
+///
+/// The values are kept as JSON, so they are sent as a JSON body, or as a multipart form
+/// when there is a file to upload.
+#[derive(Clone, Default)]
+pub struct Params {
+    fields: BTreeMap<String, Json>,
+    file: Option<(String, Upload)>,
+}
+
+// A file to upload, the rest of `InputFile`s are sent as text
+#[derive(Clone)]
+enum Upload {
+    Path(PathBuf),
+    Bytes(String, Vec<u8>),
+}
+
+impl Params {
+    pub fn new() -> Params {
+        Params::default()
+    }
+
+    /// Add a mandatory parameter.
+    pub fn add<T: ToJson>(mut self, key: &str, value: T) -> Params {
+        self.fields.insert(key.to_owned(), value.to_json());
+        self
+    }
+
+    /// Add an optional parameter, `None` values are not sent.
+    pub fn opt<T: ToJson>(self, key: &str, value: Option<T>) -> Params {
+        match value {
+            Some(v) => self.add(key, v),
+            None => self,
+        }
+    }
+
+    // The content type and the body to send them
+    fn body(&self) -> Result<(String, Vec<u8>)> {
+        match self.file {
+            Some(ref file) => multipart_body(&self.fields, file),
+            None => Ok((
+                "application/json".to_owned(),
+                Json::Object(self.fields.clone()).to_string().into_bytes(),
+            )),
+        }
+    }
+
+    // The chat where the call sends, if it has one
+    fn chat_id(&self) -> Option<Integer> {
+        self.fields.get("chat_id").and_then(|c| c.as_i64())
+    }
+
+    /// Add a media parameter, the local files and bytes are uploaded,
+    /// the file ids and URLs are sent as text.
+    pub fn file(mut self, key: &str, value: InputFile) -> Params {
+        let upload = match value {
+            InputFile::FileId(s) | InputFile::Url(s) => return self.add(key, s),
+            InputFile::Path(path) => Upload::Path(path),
+            InputFile::Bytes(name, content) => Upload::Bytes(name, content),
+        };
+        self.file = Some((key.to_owned(), upload));
+        self
+    }
+}
+
+/// Small Bot API client, every request of the bot goes through it.
+///
+/// The messages sent wait in the send queue to respect the rate limits,
+/// and the failed calls are repeated following the `RetryPolicy`.
+#[derive(Clone)]
+pub struct Client {
+    url: String,
+    file_url: String,
+    download_limit: u64,
+    http: Arc<HttpClient>,
+    queue: Arc<SendQueue>,
+    retry: RetryPolicy,
+    // Called when an enqueued call fails
+    on_error: Option<Arc<SendErrorHook>>,
+}
+
+impl Client {
+    /// Creates a client for the token in the API server `api_url`
+    /// (usually `DEFAULT_API_URL`). It doesn't check the token.
+    pub fn new(
+        api_url: &str,
+        token: &str,
+        download_limit: u64,
+        limits: RateLimits,
+        retry: RetryPolicy,
+    ) -> Client {
+        let api_url = api_url.trim_right_matches('/');
+        Client {
+            url: format!("{}/bot{}/", api_url, token),
+            file_url: format!("{}/file/bot{}/", api_url, token),
+            download_limit: download_limit,
+            http: Arc::new(HttpClient::new()),
+            queue: Arc::new(SendQueue::new(limits)),
+            retry: retry,
+            on_error: None,
+        }
+    }
+
+    // Don't retry the calls once the bot of `shutdown` is stopped
+    pub(crate) fn stop_retries_on(&mut self, shutdown: ShutdownHandle) {
+        self.retry.stop_on(shutdown);
+    }
+
+    // Set the function called when an enqueued call fails
+    pub(crate) fn on_error(&mut self, hook: Arc<SendErrorHook>) {
+        self.on_error = Some(hook);
+    }
+
+    /// Call `method` with the given parameters and decode the result.
+    ///
+    /// If Telegram answers with `retry_after`, it waits and calls it again.
+    pub fn call<T: Decodable>(&self, method: &str, params: Params) -> Result<T> {
+        self.call_json(method, params).and_then(decode)
+    }
+
+    // Call `method`, returning the result without decoding it
+    fn call_json(&self, method: &str, params: Params) -> Result<Json> {
+        let chat = if is_limited(method) {
+            params.chat_id()
+        } else {
+            None
+        };
+        let mut retries = 0;
+        loop {
+            let s = try!(self.retry.run(method, || {
+                if chat.is_some() {
+                    self.queue.acquire(chat);
+                }
+                self.post(method, &params)
+            }));
+            match retry_after(&s) {
+                Some(seconds) if retries < MAX_RETRY_AFTER => {
+                    retries += 1;
+                    if chat.is_some() {
+                        self.queue.block(chat, seconds);
+                    } else if !self.retry.wait(Duration::from_secs(seconds)) {
+                        // The bot is stopped, don't wait to call it again
+                        return response_result(&s);
+                    }
+                }
+                _ => return response_result(&s),
+            }
+        }
+    }
+
+    /// Call `method` in the background, without waiting for the answer.
+    /// The calls to the same chat are done in the same order they are enqueued,
+    /// and their errors are given to the `on_send_error` hook of the bot.
+    pub fn enqueue(&self, method: &'static str, params: Params) {
+        let client = self.clone();
+        let chat = params.chat_id();
+        self.queue.enqueue(chat, is_limited(method), move || {
+            if let Err(e) = client.call_json(method, params) {
+                if let Some(ref hook) = client.on_error {
+                    hook(method, &e);
+                }
+            }
+        });
+    }
+
+    // POST the parameters to `method`, returning the body of the response
+    fn post(&self, method: &str, params: &Params) -> Result<String> {
+        let url = format!("{}{}", self.url, method);
+        let (content_type, body) = try!(params.body());
+
+        let mut headers = Headers::new();
+        headers.set_raw("Content-Type", vec![content_type.into_bytes()]);
+
+        let mut res = try!(
+            self.http
+                .post(&url)
+                .headers(headers)
+                .body(&body[..])
+                .send()
+                .map_err(Error::Http)
+        );
+        let mut s = String::new();
+        try!(res.read_to_string(&mut s).map_err(Error::Io));
+        Ok(s)
+    }
+
+    // `getMe` method, telling apart the token rejected by Telegram (`error_code` 401)
+    pub(crate) fn check_token(&self) -> ::std::result::Result<User, BotError> {
+        let params = Params::new();
+        let s = try!(self.retry.run("getMe", || self.post("getMe", &params)));
+        if error_code(&s) == Some(401) {
+            return Err(BotError::Unauthorized);
+        }
+        decode_response(&s).map_err(BotError::from)
+    }
+
+    /// `getUpdates` method, `allowed` are the update kinds to receive (all of them if empty).
+    pub fn get_updates(
+        &self,
+        offset: Option<Integer>,
+        timeout: Integer,
+        allowed: &[String],
+    ) -> Result<Vec<Received>> {
+        let mut params = Params::new()
+            .opt("offset", offset)
+            .add("timeout", timeout);
+        if !allowed.is_empty() {
+            params = params.add("allowed_updates", allowed.to_vec());
+        }
+        // Decoded one by one, so an update that can't be decoded doesn't stop the rest
+        let updates = match try!(self.call_json("getUpdates", params)) {
+            Json::Array(updates) => updates,
+            _ => return Err(Error::Api(String::from("getUpdates didn't return a list"))),
+        };
+        Ok(updates.into_iter().map(decode_update).collect())
+    }
+
+    /// `getFile` method.
+    pub fn get_file(&self, file_id: &str) -> Result<FileInfo> {
+        self.call("getFile", Params::new().add("file_id", file_id.to_owned()))
+    }
+
+    /// Download a file with `getFile`, writing it in `out`, returns the bytes written.
+    /// It fails with a `DownloadError` if the file is bigger than the download limit
+    /// or it can't be downloaded.
+    pub fn download(&self, file_id: &str, out: &mut Write) -> Result<u64> {
+        let info = try!(self.get_file(file_id));
+        if info.file_size.map_or(false, |s| s as u64 > self.download_limit) {
+            return Err(DownloadError::TooBig.into_error());
+        }
+        let path = match info.file_path {
+            Some(path) => path,
+            None => return Err(DownloadError::NoFilePath.into_error()),
+        };
+
+        let url = format!("{}{}", self.file_url, path);
+        let res = try!(self.http.get(&url).send().map_err(Error::Http));
+        if !res.status.is_success() {
+            return Err(DownloadError::Status(res.status.to_u16()).into_error());
+        }
+        // Read one more byte than allowed to know if the file is too big
+        let written = try!(io::copy(&mut res.take(self.download_limit + 1), out).map_err(Error::Io));
+        if written > self.download_limit {
+            return Err(DownloadError::TooBig.into_error());
+        }
+        Ok(written)
+    }
+
+    /// Confirm to Telegram all the updates before `offset`, without waiting for new ones.
+    pub fn confirm_updates(&self, offset: Integer) -> Result<()> {
+        let params = Params::new()
+            .add("offset", offset)
+            .add("limit", 1)
+            .add("timeout", 0);
+        self.call_json("getUpdates", params).map(|_| ())
+    }
+}
+
+// Only the messages sent count for the rate limits, not the edits, deletes or chat actions
+fn is_limited(method: &str) -> bool {
+    method == "forwardMessage" || (method.starts_with("send") && method != "sendChatAction")
+}
+
+// Decode the response of the Bot API, that is `{"ok": true, "result": ...}` or
+// `{"ok": false, "description": ...}`
+fn decode_response<T: Decodable>(s: &str) -> Result<T> {
+    response_result(s).and_then(decode)
+}
+
+// The `result` of a response of the Bot API, without decoding it
+fn response_result(s: &str) -> Result<Json> {
+    let j = try!(Json::from_str(s).map_err(|e| Error::JsonDecode(json::DecoderError::ParseError(e))));
+
+    if j.find("ok").and_then(|ok| ok.as_boolean()) == Some(true) {
+        Ok(j.find("result").cloned().unwrap_or(Json::Null))
+    } else {
+        let description = j.find("description")
+            .and_then(|d| d.as_string())
+            .unwrap_or("Unknown error");
+        Err(Error::Api(description.to_owned()))
+    }
+}
+
+fn decode<T: Decodable>(j: Json) -> Result<T> {
+    let mut decoder = json::Decoder::new(j);
+    Decodable::decode(&mut decoder).map_err(Error::JsonDecode)
+}
+
+// The `error_code` of an error response of the Bot API
+fn error_code(s: &str) -> Option<i64> {
+    Json::from_str(s)
+        .ok()
+        .and_then(|j| j.find("error_code").and_then(|c| c.as_i64()))
+}
+
+// Seconds to wait before repeating the request, when the response is a
+// `429 Too Many Requests` error with `{"parameters": {"retry_after": N}}`
+fn retry_after(s: &str) -> Option<u64> {
+    let j = match Json::from_str(s) {
+        Ok(j) => j,
+        Err(_) => return None,
+    };
+    if j.find("ok").and_then(|ok| ok.as_boolean()) == Some(true) {
+        return None;
+    }
+    j.find_path(&["parameters", "retry_after"]).and_then(|r| r.as_u64())
+}
+
+// Build a multipart/form-data body with all the fields and the file to upload
+fn multipart_body(
+    fields: &BTreeMap<String, Json>,
+    file: &(String, Upload),
+) -> Result<(String, Vec<u8>)> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    let boundary = format!("------------------------awesomebot{:x}", nanos);
+
+    let mut body: Vec<u8> = Vec::new();
+    for (key, value) in fields {
+        let value = match *value {
+            Json::String(ref s) => s.clone(),
+            ref other => other.to_string(),
+        };
+        body.extend(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name={}\r\n\r\n{}\r\n",
+                boundary,
+                quote(key),
+                value
+            ).into_bytes(),
+        );
+    }
+
+    let (ref key, ref upload) = *file;
+    let (filename, content) = match *upload {
+        Upload::Path(ref path) => {
+            let filename = path.file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("file")
+                .to_owned();
+            let mut content = Vec::new();
+            try!(
+                fs::File::open(path)
+                    .and_then(|mut f| f.read_to_end(&mut content))
+                    .map_err(Error::Io)
+            );
+            (filename, content)
+        }
+        Upload::Bytes(ref name, ref content) => (name.clone(), content.clone()),
+    };
+    body.extend(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name={}; filename={}\r\n\
+             Content-Type: application/octet-stream\r\n\r\n",
+            boundary,
+            quote(key),
+            quote(&filename)
+        ).into_bytes(),
+    );
+    body.extend(content);
+    body.extend(format!("\r\n--{}--\r\n", boundary).into_bytes());
+
+    Ok((format!("multipart/form-data; boundary={}", boundary), body))
+}
+
+// Quote a name of the Content-Disposition header, escaping the quotes and the line
+// breaks the same as the browsers, so it can't break the header
+fn quote(name: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in name.chars() {
+        match c {
+            '"' => quoted.push_str("%22"),
+            '\r' => quoted.push_str("%0D"),
+            '\n' => quoted.push_str("%0A"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_response, error_code, quote, retry_after, Params};
+    use file::InputFile;
+    use rustc_serialize::json::Json;
+    use telegram_bot::{Error, User};
+
+    fn text(body: &[u8]) -> String {
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn params_json_body_right() {
+        let params = Params::new()
+            .add("chat_id", 12)
+            .opt("text", Some("hi".to_owned()))
+            .opt::<bool>("disable_notification", None)
+            .file("photo", InputFile::id("AgADBA"));
+        let (content_type, body) = params.body().unwrap();
+        assert_eq!(content_type, "application/json");
+        assert_eq!(text(&body), r#"{"chat_id":12,"photo":"AgADBA","text":"hi"}"#);
+        assert_eq!(params.chat_id(), Some(12));
+    }
+
+    #[test]
+    fn params_multipart_body_right() {
+        let params = Params::new()
+            .add("chat_id", 12)
+            .add("reply_markup", Json::from_str(r#"{"a":1}"#).unwrap())
+            .file("photo", InputFile::bytes("a.png", vec![1, 2, 3]));
+        let (content_type, body) = params.body().unwrap();
+        let boundary = content_type["multipart/form-data; boundary=".len()..].to_owned();
+        assert!(content_type.starts_with("multipart/form-data; boundary="));
+
+        let mut expected = format!(
+            "--{0}\r\nContent-Disposition: form-data; name=\"chat_id\"\r\n\r\n12\r\n\
+             --{0}\r\nContent-Disposition: form-data; name=\"reply_markup\"\r\n\r\n{{\"a\":1}}\r\n\
+             --{0}\r\nContent-Disposition: form-data; name=\"photo\"; filename=\"a.png\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n",
+            boundary
+        ).into_bytes();
+        expected.extend(vec![1, 2, 3]);
+        expected.extend(format!("\r\n--{}--\r\n", boundary).into_bytes());
+        assert_eq!(body, expected);
+    }
+
+    #[test]
+    fn multipart_names_quoted_right() {
+        assert_eq!(quote("a.png"), "\"a.png\"");
+        assert_eq!(quote("a\"b\r\nc.png"), "\"a%22b%0D%0Ac.png\"");
+
+        let params = Params::new().file("photo", InputFile::bytes("x\"\r\ny", vec![]));
+        let (_, body) = params.body().unwrap();
+        assert!(text(&body).contains("name=\"photo\"; filename=\"x%22%0D%0Ay\"\r\n"));
+    }
+
+    #[test]
+    fn decode_response_right() {
+        let user: User = decode_response(
+            r#"{"ok": true, "result": {"id": 1, "first_name": "Bot", "username": "abot"}}"#,
+        ).unwrap();
+        assert_eq!(user.id, 1);
+        assert_eq!(user.username, Some(String::from("abot")));
+
+        match decode_response::<User>(r#"{"ok": false, "description": "Bad Request"}"#) {
+            Err(Error::Api(ref desc)) => assert_eq!(desc, "Bad Request"),
+            other => panic!("unexpected {:?}", other),
+        }
+        match decode_response::<User>("<html>") {
+            Err(Error::JsonDecode(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn error_code_right() {
+        let unauthorized = r#"{"ok": false, "error_code": 401, "description": "Unauthorized"}"#;
+        assert_eq!(error_code(unauthorized), Some(401));
+        assert_eq!(error_code(r#"{"ok": true, "result": true}"#), None);
+        assert_eq!(error_code("<html>"), None);
+    }
+
+    #[test]
+    fn retry_after_right() {
+        let limited = r#"{"ok": false, "error_code": 429, "description": "Too Many Requests",
+                          "parameters": {"retry_after": 3}}"#;
+        assert_eq!(retry_after(limited), Some(3));
+        assert_eq!(retry_after(r#"{"ok": false, "description": "Bad Request"}"#), None);
+        assert_eq!(retry_after(r#"{"ok": true, "result": true}"#), None);
+        assert_eq!(retry_after("<html>"), None);
+    }
+}
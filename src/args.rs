@@ -0,0 +1,188 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Arguments of a command added with `typed_command`, parsed from the words written
+/// after the command. Use quotes to pass an argument with spaces: `/say "hello world" 3`.
+///
+/// It's implemented for tuples of up to 6 values that implement `FromStr` (like
+/// `(u32, String)`), for `Vec<T>` (any number of arguments) and for `()` (no arguments).
+/// Implement it for your own types to parse them in another way, the error is the
+/// message shown to the user.
+pub trait CommandArgs: Sized {
+    fn parse(args: &[String]) -> Result<Self, String>;
+}
+
+impl CommandArgs for () {
+    fn parse(args: &[String]) -> Result<(), String> {
+        if args.is_empty() {
+            Ok(())
+        } else {
+            Err("This command doesn't have arguments".to_owned())
+        }
+    }
+}
+
+impl<T: FromStr> CommandArgs for Vec<T>
+where
+    T::Err: fmt::Display,
+{
+    fn parse(args: &[String]) -> Result<Vec<T>, String> {
+        (0..args.len()).map(|i| parse_arg(args, i)).collect()
+    }
+}
+
+// Parse the argument `i`
+fn parse_arg<T: FromStr>(args: &[String], i: usize) -> Result<T, String>
+where
+    T::Err: fmt::Display,
+{
+    match args.get(i) {
+        Some(arg) => arg.parse()
+            .map_err(|e| format!("Invalid argument {} ({}): {}", i + 1, arg, e)),
+        None => Err(format!("Missing argument {}", i + 1)),
+    }
+}
+
+macro_rules! tupleargs {
+    ($n: expr, $($t: ident => $i: expr),+) => {
+        impl<$($t: FromStr),+> CommandArgs for ($($t,)+)
+        where
+            $(<$t as FromStr>::Err: fmt::Display),+
+        {
+            fn parse(args: &[String]) -> Result<($($t,)+), String> {
+                if args.len() > $n {
+                    return Err(format!("Too many arguments, expected {}", $n));
+                }
+                Ok(($(try!(parse_arg::<$t>(args, $i)),)+))
+            }
+        }
+    };
+}
+
+tupleargs!(1, A => 0);
+tupleargs!(2, A => 0, B => 1);
+tupleargs!(3, A => 0, B => 1, C => 2);
+tupleargs!(4, A => 0, B => 1, C => 2, D => 3);
+tupleargs!(5, A => 0, B => 1, C => 2, D => 3, E => 4);
+tupleargs!(6, A => 0, B => 1, C => 2, D => 3, E => 4, F => 5);
+
+// Split the text of the arguments in words, the text between quotes (`"` or `'`)
+// is a single word, and `\` escapes the next character
+pub(crate) fn split_args(text: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+                in_word = true;
+            }
+            '"' | '\'' if quote == Some(c) => quote = None,
+            '"' | '\'' if quote.is_none() => {
+                quote = Some(c);
+                in_word = true;
+            }
+            c if c.is_whitespace() && quote.is_none() => {
+                if in_word {
+                    args.push(current.clone());
+                    current.clear();
+                    in_word = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_word = true;
+            }
+        }
+    }
+    // An unclosed quote ends with the text
+    if in_word {
+        args.push(current);
+    }
+    args
+}
+
+#[cfg(test)]
+mod test {
+    use super::{split_args, CommandArgs};
+    use rustc_serialize::json::Json;
+    use std::sync::{Arc, Mutex};
+    use test::fixtures::{api_bot, fake_api, ok, test_bot, text_update};
+
+    #[test]
+    fn split_args_right() {
+        assert_eq!(split_args("3  d20"), vec!["3", "d20"]);
+        assert_eq!(
+            split_args(r#"say "hello world" 'it\'s' """#),
+            vec!["say", "hello world", "it's", ""]
+        );
+        assert!(split_args("   ").is_empty());
+    }
+
+    #[test]
+    fn command_args_right() {
+        let args = split_args("3 d20");
+        assert_eq!(<(u32, String)>::parse(&args), Ok((3, String::from("d20"))));
+        assert!(<(u32, u32)>::parse(&args).is_err());
+        assert!(<(u32,)>::parse(&args).is_err());
+        assert_eq!(
+            <(u32, String, u32)>::parse(&args),
+            Err(String::from("Missing argument 3"))
+        );
+        assert_eq!(Vec::<u32>::parse(&split_args("1 2 3")), Ok(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn typed_command_right() {
+        let rolls = Arc::new(Mutex::new(Vec::new()));
+        let mut bot = test_bot();
+        let log = rolls.clone();
+        bot.try_typed_command("roll", "/roll <dice> <sides>", move |_, _, args: (u32, u32)| {
+            log.lock().unwrap().push(args)
+        }).unwrap();
+        bot.handle_update(text_update("/roll 2 6"));
+        bot.handle_update(text_update("/roll@usernamebot 1  20"));
+        bot.handle_update(text_update("/rolling 1 20"));
+        assert_eq!(*rolls.lock().unwrap(), vec![(2, 6), (1, 20)]);
+    }
+
+    #[test]
+    fn typed_command_usage_right() {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let log = sent.clone();
+        let (mut listening, url) = fake_api(move |method, params| {
+            log.lock().unwrap().push((method.to_owned(), params.clone()));
+            ok(r#"{"message_id": 6, "date": 0, "text": "Usage",
+                "from": {"id": 1234, "first_name": "Bot"},
+                "chat": {"id": 1, "type": "private", "first_name": "A"}}"#)
+        });
+
+        let rolls = Arc::new(Mutex::new(Vec::new()));
+        let mut bot = api_bot(&url);
+        let log = rolls.clone();
+        bot.typed_command("roll", "/roll <dice> <sides>", move |_, _, args: (u32, u32)| {
+            log.lock().unwrap().push(args)
+        });
+        bot.handle_update(text_update("/roll x"));
+        listening.close().unwrap();
+
+        assert!(rolls.lock().unwrap().is_empty());
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0, "sendMessage");
+        assert_eq!(sent[0].1.find("chat_id"), Some(&Json::U64(1)));
+        assert_eq!(
+            sent[0].1.find("text"),
+            Some(&Json::String(String::from(
+                "Invalid argument 1 (x): invalid digit found in string\n\
+                 Usage: /roll <dice> <sides>"
+            )))
+        );
+    }
+}
@@ -0,0 +1,307 @@
+use std::cmp;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use api::{Client, DEFAULT_API_URL, DEFAULT_DOWNLOAD_LIMIT};
+use error::BotError;
+use offset::{MemoryOffsetStore, OffsetStore};
+use queue::RateLimits;
+use retry::RetryPolicy;
+use session::{MemorySessionStore, SessionStore};
+use telegram_bot::Integer;
+use AwesomeBot;
+
+/// The kinds of updates that Telegram can send to the bot, used to filter what to receive
+/// with `allowed_updates`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpdateKind {
+    Message,
+    EditedMessage,
+    /// The `from` of the channel posts is made up from the channel, see `Update`.
+    ChannelPost,
+    EditedChannelPost,
+    InlineQuery,
+    ChosenInlineResult,
+    CallbackQuery,
+}
+
+impl UpdateKind {
+    /// The name of the update kind in the Bot API.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            UpdateKind::Message => "message",
+            UpdateKind::EditedMessage => "edited_message",
+            UpdateKind::ChannelPost => "channel_post",
+            UpdateKind::EditedChannelPost => "edited_channel_post",
+            UpdateKind::InlineQuery => "inline_query",
+            UpdateKind::ChosenInlineResult => "chosen_inline_result",
+            UpdateKind::CallbackQuery => "callback_query",
+        }
+    }
+}
+
+/// Options used when starting the bot, check `AwesomeBot::start_with`.
+#[derive(Clone)]
+pub struct StartOptions {
+    /// Number of threads used to run the handlers, 0 is taken as 1.
+    pub pool_size: usize,
+    /// Timeout in seconds of the long polling (`getUpdates`).
+    pub poll_timeout: Integer,
+    /// Update kinds to receive, if it's empty, Telegram's default is used.
+    pub allowed_updates: Vec<UpdateKind>,
+    /// Identifier of the first update to receive, if it's `None`, the one saved in
+    /// `offset_store` is used.
+    pub offset: Option<Integer>,
+    /// Where the offset of the handled updates is saved.
+    pub offset_store: Arc<OffsetStore>,
+    /// Maximum time to wait for the running handlers when the bot is stopped, the ones
+    /// still running after it are left running in the background.
+    pub drain_timeout: Duration,
+}
+
+// The store can't be printed, only the rest of options
+impl fmt::Debug for StartOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StartOptions")
+            .field("pool_size", &self.pool_size)
+            .field("poll_timeout", &self.poll_timeout)
+            .field("allowed_updates", &self.allowed_updates)
+            .field("offset", &self.offset)
+            .field("drain_timeout", &self.drain_timeout)
+            .finish()
+    }
+}
+
+impl Default for StartOptions {
+    fn default() -> StartOptions {
+        StartOptions {
+            pool_size: 4,
+            poll_timeout: 20,
+            allowed_updates: Vec::new(),
+            offset: None,
+            offset_store: Arc::new(MemoryOffsetStore::new()),
+            drain_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Builder to create an `AwesomeBot` with custom options.
+///
+/// ``` ignore
+/// let bot = AwesomeBotBuilder::new(&token).pool_size(16).poll_timeout(60).build();
+/// ```
+#[derive(Clone)]
+pub struct AwesomeBotBuilder {
+    token: String,
+    api_url: String,
+    download_limit: u64,
+    rate_limits: RateLimits,
+    retry_policy: RetryPolicy,
+    session_store: Arc<SessionStore>,
+    options: StartOptions,
+}
+
+impl AwesomeBotBuilder {
+    /// Start a builder with the bot token and the default options.
+    pub fn new(token: &str) -> AwesomeBotBuilder {
+        AwesomeBotBuilder {
+            token: token.to_owned(),
+            api_url: DEFAULT_API_URL.to_owned(),
+            download_limit: DEFAULT_DOWNLOAD_LIMIT,
+            rate_limits: RateLimits::default(),
+            retry_policy: RetryPolicy::default(),
+            session_store: Arc::new(MemorySessionStore::new()),
+            options: StartOptions::default(),
+        }
+    }
+
+    /// Set the number of threads that run the handlers, 4 by default (at least 1).
+    pub fn pool_size(&mut self, size: usize) -> &mut AwesomeBotBuilder {
+        self.options.pool_size = cmp::max(size, 1);
+        self
+    }
+
+    /// Set the long polling timeout in seconds, 20 by default (0 is short polling).
+    /// A stopped bot waits for the poll running, so a long timeout makes the shutdown slower
+    /// when no update arrives, check `ShutdownHandle`.
+    pub fn poll_timeout(&mut self, seconds: Integer) -> &mut AwesomeBotBuilder {
+        self.options.poll_timeout = seconds;
+        self
+    }
+
+    /// Set the kinds of updates to receive.
+    pub fn allowed_updates(&mut self, kinds: &[UpdateKind]) -> &mut AwesomeBotBuilder {
+        self.options.allowed_updates = kinds.to_vec();
+        self
+    }
+
+    /// Set the identifier of the first update to receive.
+    pub fn offset(&mut self, offset: Integer) -> &mut AwesomeBotBuilder {
+        self.options.offset = Some(offset);
+        self
+    }
+
+    /// Set the store of the update offset, use a `FileOffsetStore` to continue from the
+    /// same update after a restart.
+    pub fn offset_store<S: OffsetStore + 'static>(&mut self, store: S) -> &mut AwesomeBotBuilder {
+        self.options.offset_store = Arc::new(store);
+        self
+    }
+
+    /// Set the maximum time to wait for the running handlers when the bot is stopped,
+    /// 30 seconds by default. After it, the bot stops without waiting more, check
+    /// `ShutdownHandle`.
+    pub fn drain_timeout(&mut self, timeout: Duration) -> &mut AwesomeBotBuilder {
+        self.options.drain_timeout = timeout;
+        self
+    }
+
+    /// Set the URL of the Bot API server, useful if you run your own server.
+    pub fn api_url(&mut self, url: &str) -> &mut AwesomeBotBuilder {
+        self.api_url = url.to_owned();
+        self
+    }
+
+    /// Set the maximum size in bytes of the files downloaded with `download`, 20 MB by default.
+    pub fn download_limit(&mut self, bytes: u64) -> &mut AwesomeBotBuilder {
+        self.download_limit = bytes;
+        self
+    }
+
+    /// Set the limits of the messages sent, by default the ones recommended by Telegram.
+    pub fn rate_limits(&mut self, limits: RateLimits) -> &mut AwesomeBotBuilder {
+        self.rate_limits = limits;
+        self
+    }
+
+    /// Set how the failed calls to the Bot API are retried, when receiving the updates
+    /// and when sending.
+    pub fn retry_policy(&mut self, policy: &RetryPolicy) -> &mut AwesomeBotBuilder {
+        self.retry_policy = policy.clone();
+        self
+    }
+
+    /// Set the store of the sessions of the users, use a `JsonFileSessionStore` to keep
+    /// them after a restart. By default they are kept in memory.
+    pub fn session_store<S: SessionStore + 'static>(&mut self, store: S) -> &mut AwesomeBotBuilder {
+        self.session_store = Arc::new(store);
+        self
+    }
+
+    /// Build the bot, the options will be used by `simple_start` and `webhook_start`.
+    /// This checks that the token is valid by calling `get_me`.
+    /// It panics if the token is invalid, use `try_build` to handle the error.
+    pub fn build(&self) -> AwesomeBot {
+        match self.try_build() {
+            Ok(bot) => bot,
+            Err(e) => panic!("Invalid token! ({})", e),
+        }
+    }
+
+    /// Build the bot the same as `build`, but returning the error instead of panicking.
+    pub fn try_build(&self) -> Result<AwesomeBot, BotError> {
+        if !valid_token(&self.token) {
+            return Err(BotError::MalformedToken);
+        }
+
+        let api = Client::new(
+            &self.api_url,
+            &self.token,
+            self.download_limit,
+            self.rate_limits.clone(),
+            self.retry_policy.clone(),
+        );
+        let user = try!(api.check_token());
+        let username = user.username.unwrap_or("".to_string());
+        let mut bot = AwesomeBot::with_api(api, user.id, username);
+        bot.options = self.options.clone();
+        bot.sessions = self.session_store.clone();
+        Ok(bot)
+    }
+}
+
+// Checks that the token looks like `123456:ABC-DEF1234ghIkl`
+pub(crate) fn valid_token(token: &str) -> bool {
+    let mut parts = token.splitn(2, ':');
+    let id = parts.next().unwrap_or("");
+    let secret = parts.next().unwrap_or("");
+    !id.is_empty()
+        && id.chars().all(|c| c.is_digit(10))
+        && !secret.is_empty()
+        && secret
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+#[cfg(test)]
+mod test {
+    use super::{valid_token, AwesomeBotBuilder};
+    use error::BotError;
+    use hyper::status::StatusCode;
+    use test::fixtures::{fake_http, ok};
+
+    #[test]
+    fn token_valid_right() {
+        assert!(valid_token("123456:ABC-DEF1234ghIkl-zyx57W2v1u123ew11"));
+    }
+
+    #[test]
+    fn token_malformed_wrong() {
+        assert!(!valid_token(""));
+        assert!(!valid_token("123456"));
+        assert!(!valid_token("123456:"));
+        assert!(!valid_token(":ABC-DEF"));
+        assert!(!valid_token("abc:ABC-DEF"));
+        assert!(!valid_token("123456:ABC/DEF"));
+    }
+
+    #[test]
+    fn pool_size_zero_right() {
+        let mut builder = AwesomeBotBuilder::new("1234:token");
+        assert_eq!(builder.pool_size(0).options.pool_size, 1);
+        assert_eq!(builder.pool_size(8).options.pool_size, 8);
+    }
+
+    #[test]
+    fn try_build_right() {
+        let (mut listening, url) = fake_http(|_, _| {
+            (StatusCode::Ok, ok(r#"{"id": 1234, "first_name": "Bot", "username": "abot"}"#))
+        });
+        let bot = AwesomeBotBuilder::new("1234:token").api_url(&url).try_build().unwrap();
+        listening.close().unwrap();
+        assert_eq!((bot.id, &bot.username[..]), (1234, "abot"));
+    }
+
+    #[test]
+    fn try_build_errors_right() {
+        let (mut listening, url) = fake_http(|_, _| {
+            (
+                StatusCode::Unauthorized,
+                String::from(r#"{"ok": false, "error_code": 401, "description": "Unauthorized"}"#),
+            )
+        });
+        match AwesomeBotBuilder::new("1234:token").api_url(&url).try_build() {
+            Err(BotError::Unauthorized) => {}
+            other => panic!("unexpected {:?}", other.err()),
+        }
+        listening.close().unwrap();
+
+        let (mut listening, url) = fake_http(|_, _| {
+            (
+                StatusCode::NotFound,
+                String::from(r#"{"ok": false, "error_code": 404, "description": "Not Found"}"#),
+            )
+        });
+        match AwesomeBotBuilder::new("1234:token").api_url(&url).try_build() {
+            Err(BotError::Api(ref desc)) => assert_eq!(desc, "Not Found"),
+            other => panic!("unexpected {:?}", other.err()),
+        }
+        listening.close().unwrap();
+
+        match AwesomeBotBuilder::new("1234").try_build() {
+            Err(BotError::MalformedToken) => {}
+            other => panic!("unexpected {:?}", other.err()),
+        }
+    }
+}
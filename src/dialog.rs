@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use telegram_bot::{Integer, Message};
+
+struct DialogsState {
+    timeout: Duration,
+    // By (chat, user), with the time they were set
+    states: HashMap<(Integer, Integer), (String, Instant)>,
+    // When the abandoned dialogs are forgotten again
+    next_cleanup: Instant,
+}
+
+// The states of the dialogs
+pub(crate) struct Dialogs {
+    state: Mutex<DialogsState>,
+}
+
+impl Dialogs {
+    pub fn new(timeout: Duration) -> Dialogs {
+        Dialogs {
+            state: Mutex::new(DialogsState {
+                timeout: timeout,
+                states: HashMap::new(),
+                next_cleanup: Instant::now() + timeout,
+            }),
+        }
+    }
+
+    // Change the timeout, also for the dialogs already started
+    pub fn set_timeout(&self, timeout: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.timeout = timeout;
+        state.next_cleanup = Instant::now() + timeout;
+    }
+
+    pub fn get(&self, key: (Integer, Integer)) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        let timeout = state.timeout;
+        let expired = match state.states.get(&key) {
+            Some(&(ref s, since)) if since.elapsed() < timeout => return Some(s.clone()),
+            Some(_) => true,
+            None => false,
+        };
+        if expired {
+            state.states.remove(&key);
+        }
+        None
+    }
+
+    pub fn set(&self, key: (Integer, Integer), s: &str) {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        // Forget the dialogs abandoned, only once per timeout so it isn't done in every change
+        if now >= state.next_cleanup {
+            let timeout = state.timeout;
+            state.states.retain(|_, &mut (_, since)| since.elapsed() < timeout);
+            state.next_cleanup = now + timeout;
+        }
+        state.states.insert(key, (s.to_owned(), now));
+    }
+
+    pub fn clear(&self, key: (Integer, Integer)) {
+        self.state.lock().unwrap().states.remove(&key);
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.state.lock().unwrap().states.len()
+    }
+}
+
+/// The dialog with a user in a chat, created by `AwesomeBot::dialog`.
+///
+/// A dialog is in a named state, while it's in a state, the messages of the user in that chat
+/// go to the handlers added with `state_fn` for that state, instead of the normal routes.
+/// The state is forgotten after the dialog timeout, or when the user sends `/cancel`
+/// (check `AwesomeBot::cancel_command`).
+///
+/// ``` ignore
+/// fn start(bot: &AwesomeBot, msg: &Message, _: String) {
+///     bot.answer(msg).text("What's your name?").end();
+///     bot.dialog(msg).set("name");
+/// }
+///
+/// fn name(bot: &AwesomeBot, msg: &Message) {
+///     bot.answer(msg).text("How old are you?").end();
+///     bot.dialog(msg).set("age");
+/// }
+///
+/// bot.simple_command("start", start).state_fn("name", name).state_fn("age", age);
+/// ```
+#[derive(Clone)]
+pub struct Dialog {
+    dialogs: Arc<Dialogs>,
+    key: (Integer, Integer),
+}
+
+impl Dialog {
+    pub(crate) fn new(dialogs: Arc<Dialogs>, msg: &Message) -> Dialog {
+        Dialog {
+            dialogs: dialogs,
+            key: (msg.chat.id(), msg.from.id),
+        }
+    }
+
+    /// The current state, `None` if the dialog isn't in any state.
+    pub fn state(&self) -> Option<String> {
+        self.dialogs.get(self.key)
+    }
+
+    /// Move the dialog to `state`, the next messages go to its handlers.
+    pub fn set(&self, state: &str) {
+        self.dialogs.set(self.key, state);
+    }
+
+    /// End the dialog, the next messages go to the normal routes.
+    pub fn end(&self) {
+        self.dialogs.clear(self.key);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Dialogs;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+    use telegram_bot::MessageType;
+    use test::fixtures::{test_bot, text_update};
+
+    #[test]
+    fn dialog_states_right() {
+        let dialogs = Dialogs::new(Duration::from_millis(50));
+        assert_eq!(dialogs.get((1, 2)), None);
+        dialogs.set((1, 2), "name");
+        assert_eq!(dialogs.get((1, 2)), Some(String::from("name")));
+        assert_eq!(dialogs.get((1, 3)), None);
+        dialogs.clear((1, 2));
+        assert_eq!(dialogs.get((1, 2)), None);
+
+        dialogs.set((1, 2), "age");
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(dialogs.get((1, 2)), None);
+    }
+
+    #[test]
+    fn dialog_cleanup_right() {
+        let dialogs = Dialogs::new(Duration::from_millis(50));
+        dialogs.set((1, 2), "name");
+        dialogs.set((1, 3), "name");
+        thread::sleep(Duration::from_millis(60));
+        // The abandoned dialogs are forgotten once the timeout passes
+        dialogs.set((1, 4), "name");
+        assert_eq!(dialogs.len(), 1);
+    }
+
+    #[test]
+    fn dialog_timeout_keeps_dialogs_right() {
+        let mut bot = test_bot();
+        let msg = text_update("Hi").message.unwrap();
+        bot.dialog(&msg).set("name");
+        bot.dialog_timeout(Duration::from_millis(50));
+        assert_eq!(bot.dialog(&msg).state(), Some(String::from("name")));
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(bot.dialog(&msg).state(), None);
+    }
+
+    #[test]
+    fn cancel_command_right() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut bot = test_bot();
+        let state = log.clone();
+        bot.state_fn("name", move |_, msg| {
+            if let MessageType::Text(ref t) = msg.msg {
+                state.lock().unwrap().push(t.clone());
+            }
+        });
+        let (commands, every) = (Arc::new(Mutex::new(0)), Arc::new(Mutex::new(0)));
+        let (count, any) = (commands.clone(), every.clone());
+        bot.simple_command("stop", move |_, _, _| *count.lock().unwrap() += 1);
+        bot.any_fn(move |_, _| *any.lock().unwrap() += 1);
+        bot.cancel_command(Some("stop"));
+        let msg = text_update("Hi").message.unwrap();
+        bot.dialog(&msg).set("name");
+
+        // `/cancel` isn't the cancel command now, it goes to the state
+        bot.handle_update(text_update("/cancel"));
+        assert_eq!(*log.lock().unwrap(), vec!["/cancel"]);
+        // It ends the dialog without reaching the routes, but every message is seen
+        bot.handle_update(text_update("/stop@usernamebot"));
+        assert_eq!(bot.dialog(&msg).state(), None);
+        assert_eq!(*commands.lock().unwrap(), 0);
+        assert_eq!(*every.lock().unwrap(), 2);
+        // Outside of a dialog it's a normal command
+        bot.handle_update(text_update("/stop"));
+        assert_eq!(*commands.lock().unwrap(), 1);
+
+        // Without cancel command, the dialogs only end from the handlers
+        bot.cancel_command(None);
+        bot.dialog(&msg).set("name");
+        bot.handle_update(text_update("/stop"));
+        assert_eq!(*log.lock().unwrap(), vec!["/cancel", "/stop"]);
+        assert_eq!(*commands.lock().unwrap(), 1);
+    }
+}
@@ -0,0 +1,112 @@
+/// How the routes that match an update are run, set it with `AwesomeBot::dispatch_policy`.
+///
+/// The routes are tried from the highest priority to the lowest (check `AwesomeBot::priority`),
+/// and the ones with the same priority in the order they were added.
+/// The handlers of every update (`any_fn`, `callback_query_fn` and `inline_query_fn`)
+/// always run, and they don't count as a match.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DispatchPolicy {
+    /// Run all the routes that match, the default.
+    All,
+    /// Run only the first route that matches.
+    First,
+    /// Run the routes that match until one of the handlers calls `stop_propagation`.
+    Priority,
+}
+
+impl Default for DispatchPolicy {
+    fn default() -> DispatchPolicy {
+        DispatchPolicy::All
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DispatchPolicy;
+    use std::sync::{Arc, Mutex};
+    use telegram_bot::Message;
+    use test::fixtures::{test_bot, text_update, update};
+    use AwesomeBot;
+
+    // A bot whose routes log their name when they run
+    fn log_bot(log: &Arc<Mutex<Vec<&'static str>>>) -> AwesomeBot {
+        let mut bot = test_bot();
+        let logger = |name: &'static str| {
+            let log = log.clone();
+            move |_: &AwesomeBot, _: &Message, _: String| log.lock().unwrap().push(name)
+        };
+        bot.simple_regex("^Hi$", logger("hi"));
+        let any = log.clone();
+        bot.any_fn(move |_, _| any.lock().unwrap().push("any"));
+        let stop = log.clone();
+        bot.simple_regex("H", move |bot, _, _| {
+            stop.lock().unwrap().push("stop");
+            bot.stop_propagation();
+        }).priority(1);
+        bot.simple_regex("i", logger("i"));
+        bot
+    }
+
+    #[test]
+    fn dispatch_policy_right() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut bot = log_bot(&log);
+        for &(policy, expected) in &[
+            (DispatchPolicy::All, &["stop", "hi", "any", "i"][..]),
+            (DispatchPolicy::First, &["stop", "any"][..]),
+            (DispatchPolicy::Priority, &["stop", "any"][..]),
+        ] {
+            bot.dispatch_policy(policy);
+            log.lock().unwrap().clear();
+            bot.handle_update(text_update("Hi"));
+            assert_eq!(*log.lock().unwrap(), expected);
+        }
+
+        // Without stopping, the priority policy runs all of them
+        log.lock().unwrap().clear();
+        bot.handle_update(text_update("hi"));
+        assert_eq!(*log.lock().unwrap(), vec!["any", "i"]);
+    }
+
+    #[test]
+    fn dispatch_callback_any_right() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut bot = test_bot();
+        bot.dispatch_policy(DispatchPolicy::First);
+        let any = log.clone();
+        bot.callback_query_fn(move |_, _| any.lock().unwrap().push("any"));
+        for &name in &["a", "b"] {
+            let log = log.clone();
+            bot.callback("^a$", move |_, _, _, _| log.lock().unwrap().push(name));
+        }
+
+        // `callback_query_fn` doesn't count as a match, the first route still runs
+        bot.handle_update(update(
+            r#"{"update_id": 1, "callback_query": {"id": "q", "data": "a",
+                "from": {"id": 2, "first_name": "A"}}}"#,
+        ));
+        assert_eq!(*log.lock().unwrap(), vec!["any", "a"]);
+    }
+
+    #[test]
+    fn dispatch_after_panic_right() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut bot = test_bot();
+        bot.simple_regex("^Hi$", |_, _, _| -> () { panic!("first handler panicked") });
+        let handler = log.clone();
+        bot.simple_regex("^Hi$", move |_, _, _| handler.lock().unwrap().push(String::from("second")));
+        let panics = log.clone();
+        bot.on_panic(move |_, _, route, message| {
+            panics.lock().unwrap().push(format!("{}: {}", route, message))
+        });
+        let unhandled = log.clone();
+        bot.unhandled_fn(move |_, _| unhandled.lock().unwrap().push(String::from("unhandled")));
+
+        // The panic only stops its handler, and it counts as a match
+        bot.handle_update(text_update("Hi"));
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["#0 simple_regex(^Hi$): first handler panicked", "second"]
+        );
+    }
+}
@@ -0,0 +1,246 @@
+use api::{Client, Params};
+use keyboard::{Keyboard, Markup};
+use send::Finisher;
+use telegram_bot::*;
+
+/// EditBuilder it's a builder struct that allows you to modify messages already sent,
+/// you will use it with the `edit` and `edit_message` methods of `AwesomeBot`
+///
+/// Only inline keyboards can be used in edited messages.
+#[derive(Clone)]
+pub struct EditBuilder {
+    chat_id: Integer,
+    message_id: Integer,
+    bot: Client,
+}
+
+impl EditBuilder {
+    pub(crate) fn new(chat_id: Integer, message_id: Integer, bot: Client) -> EditBuilder {
+        EditBuilder {
+            chat_id: chat_id,
+            message_id: message_id,
+            bot: bot,
+        }
+    }
+
+    /// Start a constructor to change the text of the message.
+    pub fn text(self, t: &str) -> EditText {
+        EditText {
+            edit: self,
+            text: t.to_string(),
+            parse_mode: None,
+            disable_webpage_preview: None,
+            reply_markup: None,
+        }
+    }
+
+    /// Start a constructor to change the caption of the message.
+    pub fn caption(self, c: &str) -> EditCaption {
+        EditCaption {
+            edit: self,
+            caption: c.to_string(),
+            reply_markup: None,
+        }
+    }
+
+    /// Start a constructor to change only the keyboard of the message,
+    /// if no keyboard is set, the keyboard is removed.
+    pub fn reply_markup(self) -> EditReplyMarkup {
+        EditReplyMarkup {
+            edit: self,
+            reply_markup: None,
+        }
+    }
+
+    fn params(&self) -> Params {
+        Params::new()
+            .add("chat_id", self.chat_id)
+            .add("message_id", self.message_id)
+    }
+}
+
+macro_rules! addeditkeyboardfuncs {
+    ($name:ident) => {
+        impl $name {
+            /// Set a `Markup` to send, but instead of this, use the `keyboard` method
+            pub fn markup(&mut self, m: Markup) -> &mut $name {
+                self.reply_markup = Some(m);
+                self
+            }
+
+            /// Set the inline keyboard of the message. It panics with a reply keyboard,
+            /// they can't be used in edited messages.
+            pub fn keyboard(&mut self, k: Keyboard) -> &mut $name {
+                assert!(k.is_inline(), "Only inline keyboards can be used in edited messages");
+                self.reply_markup = Some(Markup::from(k));
+                self
+            }
+        }
+    };
+}
+
+/// Transparent struct built by `EditBuilder` to edit the text of messages.
+pub struct EditText {
+    edit: EditBuilder,
+    text: String,
+    parse_mode: Option<ParseMode>,
+    disable_webpage_preview: Option<bool>,
+    reply_markup: Option<Markup>,
+}
+
+impl EditText {
+    /// Set `ParseMode` for the message
+    pub fn parse_mode(&mut self, p: ParseMode) -> &mut EditText {
+        self.parse_mode = Some(p);
+        self
+    }
+
+    /// Set `true` to disable the link preview in the message.
+    pub fn disable_preview(&mut self, d: bool) -> &mut EditText {
+        self.disable_webpage_preview = Some(d);
+        self
+    }
+}
+
+addeditkeyboardfuncs!(EditText);
+
+impl EditText {
+    fn request(&self) -> (&Client, &'static str, Params) {
+        let params = self.edit
+            .params()
+            .add("text", self.text.clone())
+            .opt("parse_mode", self.parse_mode.map(|p| p.to_string()))
+            .opt("disable_web_page_preview", self.disable_webpage_preview)
+            .opt("reply_markup", self.reply_markup.clone());
+        (&self.edit.bot, "editMessageText", params)
+    }
+}
+
+finisher!(EditText, Message);
+
+/// Transparent struct built by `EditBuilder` to edit the caption of messages.
+pub struct EditCaption {
+    edit: EditBuilder,
+    caption: String,
+    reply_markup: Option<Markup>,
+}
+
+addeditkeyboardfuncs!(EditCaption);
+
+impl EditCaption {
+    fn request(&self) -> (&Client, &'static str, Params) {
+        let params = self.edit
+            .params()
+            .add("caption", self.caption.clone())
+            .opt("reply_markup", self.reply_markup.clone());
+        (&self.edit.bot, "editMessageCaption", params)
+    }
+}
+
+finisher!(EditCaption, Message);
+
+/// Transparent struct built by `EditBuilder` to edit the keyboard of messages.
+pub struct EditReplyMarkup {
+    edit: EditBuilder,
+    reply_markup: Option<Markup>,
+}
+
+addeditkeyboardfuncs!(EditReplyMarkup);
+
+impl EditReplyMarkup {
+    fn request(&self) -> (&Client, &'static str, Params) {
+        let params = self.edit
+            .params()
+            .opt("reply_markup", self.reply_markup.clone());
+        (&self.edit.bot, "editMessageReplyMarkup", params)
+    }
+}
+
+finisher!(EditReplyMarkup, Message);
+
+/// Struct built by `AwesomeBot::delete` to delete messages.
+pub struct DeleteMessage {
+    edit: EditBuilder,
+}
+
+impl DeleteMessage {
+    pub(crate) fn new(edit: EditBuilder) -> DeleteMessage {
+        DeleteMessage { edit: edit }
+    }
+}
+
+impl DeleteMessage {
+    fn request(&self) -> (&Client, &'static str, Params) {
+        (&self.edit.bot, "deleteMessage", self.edit.params())
+    }
+}
+
+finisher!(DeleteMessage, bool);
+
+#[cfg(test)]
+mod test {
+    use keyboard::KeyboardBuilder;
+    use rustc_serialize::json::{Json, ToJson};
+    use send::Finisher;
+    use std::sync::{Arc, Mutex};
+    use telegram_bot::ParseMode;
+    use test::fixtures::{api_bot, fake_api, ok, text_update};
+
+    #[test]
+    fn edit_and_delete_right() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let log = calls.clone();
+        let (mut listening, url) = fake_api(move |method, params| {
+            log.lock().unwrap().push((method.to_owned(), params.clone()));
+            match method {
+                "deleteMessage" => ok("true"),
+                _ => ok(r#"{"message_id": 5, "date": 0, "text": "new",
+                            "from": {"id": 1234, "first_name": "Bot"},
+                            "chat": {"id": 1, "type": "private", "first_name": "A"}}"#),
+            }
+        });
+        let bot = api_bot(&url);
+        let msg = text_update("old").message.unwrap();
+        let kb = KeyboardBuilder::inline().callback("1", "one").build();
+
+        let edited = bot.edit_message(&msg)
+            .text("new")
+            .parse_mode(ParseMode::Markdown)
+            .disable_preview(true)
+            .keyboard(kb.clone())
+            .end()
+            .unwrap();
+        assert_eq!(edited.message_id, 5);
+        bot.edit(1, 5).caption("photo").end().unwrap();
+        bot.edit(1, 5).reply_markup().end().unwrap();
+        assert!(bot.delete(1, 5).end().unwrap());
+        listening.close().unwrap();
+
+        let calls = calls.lock().unwrap();
+        let methods: Vec<&str> = calls.iter().map(|&(ref m, _)| &m[..]).collect();
+        assert_eq!(
+            methods,
+            vec!["editMessageText", "editMessageCaption", "editMessageReplyMarkup", "deleteMessage"]
+        );
+        for &(_, ref params) in calls.iter() {
+            assert_eq!(params.find("chat_id"), Some(&Json::U64(1)));
+            assert_eq!(params.find("message_id"), Some(&Json::U64(5)));
+        }
+        let text = &calls[0].1;
+        assert_eq!(text.find("text"), Some(&"new".to_json()));
+        assert_eq!(text.find("parse_mode"), Some(&"Markdown".to_json()));
+        assert_eq!(text.find("disable_web_page_preview"), Some(&Json::Boolean(true)));
+        assert_eq!(text.find("reply_markup"), Some(&kb.to_json()));
+        assert_eq!(calls[1].1.find("caption"), Some(&"photo".to_json()));
+        // Without keyboard, it's removed
+        assert_eq!(calls[2].1.find("reply_markup"), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn edit_reply_keyboard_wrong() {
+        let bot = api_bot("http://127.0.0.1:1");
+        let kb = KeyboardBuilder::reply().button("a").build();
+        bot.edit(1, 5).reply_markup().keyboard(kb);
+    }
+}
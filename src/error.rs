@@ -0,0 +1,149 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+use keyboard::Button;
+use regex;
+use telegram_bot::Error;
+
+/// Errors that can happen while creating an `AwesomeBot`, returned by `try_new`,
+/// `try_from_env` and `AwesomeBotBuilder::try_build`.
+#[derive(Debug)]
+pub enum BotError {
+    /// The environment variable with the token can't be read.
+    MissingEnvVar(String),
+    /// The token doesn't have the `<bot id>:<secret>` format.
+    MalformedToken,
+    /// Telegram rejected the token (the Bot API answered with the error code 401).
+    Unauthorized,
+    /// The Bot API answered with an error.
+    Api(String),
+    /// The Bot API couldn't be reached or the answer couldn't be read.
+    Network(Error),
+}
+
+impl From<Error> for BotError {
+    fn from(e: Error) -> BotError {
+        match e {
+            Error::Api(desc) => BotError::Api(desc),
+            e => BotError::Network(e),
+        }
+    }
+}
+
+impl fmt::Display for BotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BotError::MissingEnvVar(ref var) => write!(f, "Environment variable {} error.", var),
+            BotError::MalformedToken => write!(f, "Malformed token"),
+            BotError::Unauthorized => write!(f, "Unauthorized token"),
+            BotError::Api(ref desc) => write!(f, "Telegram error: {}", desc),
+            BotError::Network(ref e) => write!(f, "Network error: {}", e),
+        }
+    }
+}
+
+impl error::Error for BotError {
+    fn description(&self) -> &str {
+        match *self {
+            BotError::MissingEnvVar(_) => "missing environment variable",
+            BotError::MalformedToken => "malformed token",
+            BotError::Unauthorized => "unauthorized token",
+            BotError::Api(_) => "telegram error",
+            BotError::Network(_) => "network error",
+        }
+    }
+}
+
+/// Error of a route with an invalid regular expression, returned by `try_command`,
+/// `try_regex` and the rest of `try_` methods of `AwesomeBot`.
+#[derive(Debug)]
+pub struct RouteError {
+    /// The pattern compiled, in the commands it's the one with the bot mention added.
+    pub pattern: String,
+    /// The error of the regular expression.
+    pub error: regex::Error,
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid route pattern {}: {}", self.pattern, self.error)
+    }
+}
+
+impl error::Error for RouteError {
+    fn description(&self) -> &str {
+        "invalid route pattern"
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        Some(&self.error)
+    }
+}
+
+/// Error of a reply keyboard with buttons that only work in inline keyboards (URLs,
+/// callbacks and inline queries), returned by `KeyboardBuilder::try_build`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyboardError {
+    /// The first inline button found.
+    pub button: Button,
+}
+
+impl fmt::Display for KeyboardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The button {} only works in inline keyboards", self.button.text())
+    }
+}
+
+impl error::Error for KeyboardError {
+    fn description(&self) -> &str {
+        "inline button in a reply keyboard"
+    }
+}
+
+/// Why a download failed, the download methods of `AwesomeBot` return it inside an
+/// `Error::Io` (get it with `get_ref` and `downcast_ref`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DownloadError {
+    /// There isn't any file to download, like in an empty `Vec<PhotoSize>`.
+    NoFile,
+    /// Telegram didn't give the path to download the file.
+    NoFilePath,
+    /// The file is bigger than the download limit.
+    TooBig,
+    /// The file server answered with this HTTP status code.
+    Status(u16),
+}
+
+impl DownloadError {
+    pub(crate) fn into_error(self) -> Error {
+        let kind = match self {
+            DownloadError::NoFile | DownloadError::NoFilePath => io::ErrorKind::NotFound,
+            DownloadError::TooBig => io::ErrorKind::InvalidData,
+            DownloadError::Status(_) => io::ErrorKind::Other,
+        };
+        Error::Io(io::Error::new(kind, self))
+    }
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DownloadError::NoFile => write!(f, "There isn't any file to download"),
+            DownloadError::NoFilePath => write!(f, "The file can't be downloaded"),
+            DownloadError::TooBig => write!(f, "The file is bigger than the download limit"),
+            DownloadError::Status(code) => write!(f, "The download failed with status {}", code),
+        }
+    }
+}
+
+impl error::Error for DownloadError {
+    fn description(&self) -> &str {
+        match *self {
+            DownloadError::NoFile => "no file to download",
+            DownloadError::NoFilePath => "file without path",
+            DownloadError::TooBig => "file too big",
+            DownloadError::Status(_) => "download failed",
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::path::{Path, PathBuf};
+
+use telegram_bot::{Audio, Document, Integer, PhotoSize, Sticker, Video, Voice};
+
+/// A file to send in the media methods of `SendBuilder` (`photo`, `audio`, `document`, ...).
+///
+/// The local files and the bytes are uploaded, the file ids and URLs are sent directly.
+///
+/// A `Path` or a `PathBuf` can be used too, they are uploaded. The strings can't, since a
+/// string could be a path, a file id or an URL: say which one with `InputFile::path`,
+/// `InputFile::id` or `InputFile::url`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InputFile {
+    /// The id of a file already in Telegram's servers.
+    FileId(String),
+    /// The path of a local file to upload.
+    Path(PathBuf),
+    /// A file in memory to upload: (file name, content).
+    Bytes(String, Vec<u8>),
+    /// An URL that Telegram will download.
+    Url(String),
+}
+
+impl InputFile {
+    /// A file id.
+    pub fn id(id: &str) -> InputFile {
+        InputFile::FileId(id.to_owned())
+    }
+
+    /// A local file.
+    pub fn path<P: AsRef<Path>>(path: P) -> InputFile {
+        InputFile::Path(path.as_ref().to_path_buf())
+    }
+
+    /// A file in memory, the name is used by Telegram to guess the type of the file.
+    pub fn bytes(name: &str, content: Vec<u8>) -> InputFile {
+        InputFile::Bytes(name.to_owned(), content)
+    }
+
+    /// An URL.
+    pub fn url(url: &str) -> InputFile {
+        InputFile::Url(url.to_owned())
+    }
+
+    /// Returns `true` if the file has to be uploaded.
+    pub fn is_upload(&self) -> bool {
+        match *self {
+            InputFile::Path(_) | InputFile::Bytes(_, _) => true,
+            InputFile::FileId(_) | InputFile::Url(_) => false,
+        }
+    }
+}
+
+impl<'a> From<&'a Path> for InputFile {
+    fn from(p: &'a Path) -> InputFile {
+        InputFile::path(p)
+    }
+}
+
+impl From<PathBuf> for InputFile {
+    fn from(p: PathBuf) -> InputFile {
+        InputFile::Path(p)
+    }
+}
+
+/// Information of a file in Telegram's servers, returned by `getFile`.
+#[derive(RustcDecodable, Debug, Clone)]
+pub struct FileInfo {
+    pub file_id: String,
+    pub file_size: Option<Integer>,
+    /// Path to download the file, it's valid at least for one hour.
+    pub file_path: Option<String>,
+}
+
+/// Something that references a file in Telegram's servers, used in the download methods
+/// of `AwesomeBot`.
+///
+/// It's implemented for the file ids (`str` and `String`) and the media types received in the
+/// handlers. A list of `PhotoSize` (like the one received in `photo_fn`) is the same image in
+/// different sizes, the biggest one is used.
+pub trait FileSource {
+    /// The id of the file, `None` if there isn't any file.
+    fn file_id(&self) -> Option<String>;
+}
+
+impl FileSource for str {
+    fn file_id(&self) -> Option<String> {
+        Some(self.to_owned())
+    }
+}
+
+impl FileSource for String {
+    fn file_id(&self) -> Option<String> {
+        Some(self.clone())
+    }
+}
+
+impl FileSource for [PhotoSize] {
+    fn file_id(&self) -> Option<String> {
+        self.iter()
+            .max_by_key(|p| (p.width * p.height, p.file_size.unwrap_or(0)))
+            .map(|p| p.file_id.clone())
+    }
+}
+
+impl FileSource for Vec<PhotoSize> {
+    fn file_id(&self) -> Option<String> {
+        self[..].file_id()
+    }
+}
+
+macro_rules! filesource {
+    ($($t: ty),*) => {
+        $(
+            impl FileSource for $t {
+                fn file_id(&self) -> Option<String> {
+                    Some(self.file_id.clone())
+                }
+            }
+        )*
+    }
+}
+
+filesource!(PhotoSize, Document, Audio, Voice, Video, Sticker);
+
+#[cfg(test)]
+mod test {
+    use super::{FileSource, InputFile};
+    use std::path::{Path, PathBuf};
+    use telegram_bot::PhotoSize;
+
+    #[test]
+    fn input_file_right() {
+        assert_eq!(
+            InputFile::id("AgADBAADq6cxG"),
+            InputFile::FileId(String::from("AgADBAADq6cxG"))
+        );
+        assert_eq!(
+            InputFile::from(Path::new("files/test.jpg")),
+            InputFile::Path(PathBuf::from("files/test.jpg"))
+        );
+        assert_eq!(
+            InputFile::from(PathBuf::from("files/test.jpg")),
+            InputFile::path("files/test.jpg")
+        );
+        assert!(InputFile::path("files/test.jpg").is_upload());
+        assert!(!InputFile::id("AgADBAADq6cxG").is_upload());
+        assert!(InputFile::bytes("a.png", vec![1, 2, 3]).is_upload());
+        assert!(!InputFile::url("http://example.com/a.png").is_upload());
+    }
+
+    #[test]
+    fn file_source_biggest_photo_right() {
+        let photo = |id: &str, side| PhotoSize {
+            file_id: String::from(id),
+            width: side,
+            height: side,
+            file_size: None,
+        };
+        let photos = vec![photo("small", 90), photo("big", 800), photo("medium", 320)];
+        assert_eq!(photos.file_id(), Some(String::from("big")));
+        assert_eq!(Vec::<PhotoSize>::new().file_id(), None);
+    }
+}
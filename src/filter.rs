@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use builder::UpdateKind;
+use telegram_bot::{Chat, Integer, Message};
+use update::Update;
+
+pub(crate) type CustomFilter = Fn(&Message) -> bool + Send + Sync;
+
+// A condition that the updates must pass to reach a route, added with
+// `only_private`, `only_groups`, `from_users`, `in_chats` and `custom`
+#[derive(Clone)]
+pub(crate) enum Filter {
+    Private,
+    Groups,
+    Users(Vec<Integer>),
+    Chats(Vec<Integer>),
+    Custom(Arc<CustomFilter>),
+}
+
+impl Filter {
+    // `message` and `user` are the ones of the update, from `update_parts`.
+    // Without message (inline queries) only the user can be checked.
+    pub fn check(&self, message: Option<&Message>, user: Option<Integer>) -> bool {
+        match *self {
+            Filter::Private => message.map_or(false, |m| match m.chat {
+                Chat::Private { .. } => true,
+                _ => false,
+            }),
+            Filter::Groups => message.map_or(false, |m| match m.chat {
+                Chat::Group { .. } => true,
+                _ => false,
+            }),
+            Filter::Users(ref ids) => user.map_or(false, |u| ids.contains(&u)),
+            Filter::Chats(ref ids) => message.map_or(false, |m| ids.contains(&m.chat.id())),
+            Filter::Custom(ref f) => message.map_or(false, |m| f(m)),
+        }
+    }
+}
+
+// The message of the update being handled (for callback queries, the message with the
+// button) and the user that sent it
+pub(crate) fn update_parts(
+    update: &Update,
+    kind: UpdateKind,
+) -> (Option<&Message>, Option<Integer>) {
+    let message = match kind {
+        UpdateKind::Message => update.message.as_ref(),
+        UpdateKind::EditedMessage => update.edited_message.as_ref(),
+        UpdateKind::ChannelPost => update.channel_post.as_ref(),
+        UpdateKind::EditedChannelPost => update.edited_channel_post.as_ref(),
+        UpdateKind::CallbackQuery => {
+            let query = update.callback_query.as_ref();
+            return (
+                query.and_then(|q| q.message.as_ref()),
+                query.map(|q| q.from.id),
+            );
+        }
+        UpdateKind::InlineQuery => return (None, update.inline_query.as_ref().map(|q| q.from.id)),
+        UpdateKind::ChosenInlineResult => {
+            return (None, update.chosen_inline_result.as_ref().map(|c| c.from.id))
+        }
+    };
+    (message, message.map(|m| m.from.id))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use telegram_bot::Message;
+    use test::fixtures::{test_bot, text_update};
+    use AwesomeBot;
+
+    #[test]
+    fn route_filters_right() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut bot = test_bot();
+        let logger = |name: &'static str| {
+            let log = log.clone();
+            move |_: &AwesomeBot, _: &Message| log.lock().unwrap().push(name)
+        };
+        bot.any_fn(logger("private")).only_private();
+        bot.any_fn(logger("groups")).only_groups();
+        bot.any_fn(logger("user 2")).from_users(&[2, 4]);
+        bot.any_fn(logger("user 3")).from_users(&[3]);
+        bot.any_fn(logger("chat 1"))
+            .in_chats(&[1])
+            .custom(|msg| msg.message_id == 5);
+        bot.any_fn(logger("custom")).custom(|_| false);
+        bot.handle_update(text_update("Hi"));
+        assert_eq!(*log.lock().unwrap(), vec!["private", "user 2", "chat 1"]);
+    }
+}
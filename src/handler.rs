@@ -0,0 +1,148 @@
+use std::any::Any;
+use std::error;
+use std::fmt;
+use std::sync::Arc;
+
+use telegram_bot::Error;
+use update::{InvalidUpdate, Update};
+use AwesomeBot;
+
+/// Error returned by a handler, anything that converts to it (any error type,
+/// `String` or `&str`) can be returned.
+pub type HandlerError = Box<error::Error + Send + Sync>;
+
+/// What the handlers can return, `()` or a `Result<(), E>`.
+///
+/// When a handler returns an error, it's given to the `on_error` hook of the bot.
+///
+/// ``` ignore
+/// fn hello(bot: &AwesomeBot, msg: &Message, _: String) -> Result<()> {
+///     try!(bot.answer(msg).text("Hello!").end());
+///     Ok(())
+/// }
+/// ```
+pub trait HandlerResult {
+    fn into_result(self) -> Result<(), HandlerError>;
+}
+
+impl HandlerResult for () {
+    fn into_result(self) -> Result<(), HandlerError> {
+        Ok(())
+    }
+}
+
+impl<E: Into<HandlerError>> HandlerResult for Result<(), E> {
+    fn into_result(self) -> Result<(), HandlerError> {
+        self.map_err(|e| e.into())
+    }
+}
+
+/// Identifies a route, it's given to the hooks of the bot to know which handler failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RouteId {
+    /// Position of the route, in the order they were added, starting at 0.
+    pub index: usize,
+    /// Method used to add the route, like `"command"` or `"photo_fn"`.
+    pub method: &'static str,
+    /// The regular expression of the route, if it has one.
+    pub pattern: Option<String>,
+}
+
+impl fmt::Display for RouteId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.pattern {
+            Some(ref p) => write!(f, "#{} {}({})", self.index, self.method, p),
+            None => write!(f, "#{} {}", self.index, self.method),
+        }
+    }
+}
+
+pub(crate) type ErrorHook = Fn(&AwesomeBot, &Update, &RouteId, &HandlerError) + Send + Sync;
+pub(crate) type PanicHook = Fn(&AwesomeBot, &Update, &RouteId, &str) + Send + Sync;
+pub(crate) type SendErrorHook = Fn(&str, &Error) + Send + Sync;
+pub(crate) type InvalidUpdateHook = Fn(&AwesomeBot, &InvalidUpdate) + Send + Sync;
+
+// The hooks of the bot, called when the handlers fail
+#[derive(Clone, Default)]
+pub(crate) struct Hooks {
+    pub on_error: Option<Arc<ErrorHook>>,
+    pub on_panic: Option<Arc<PanicHook>>,
+    pub on_invalid_update: Option<Arc<InvalidUpdateHook>>,
+}
+
+impl Hooks {
+    // Report the error of a handler, nothing is done without a hook
+    pub fn error(&self, bot: &AwesomeBot, update: &Update, route: &RouteId, e: &HandlerError) {
+        if let Some(ref hook) = self.on_error {
+            hook(bot, update, route, e)
+        }
+    }
+
+    // Report the panic of a handler, nothing is done without a hook
+    pub fn panic(&self, bot: &AwesomeBot, update: &Update, route: &RouteId, payload: &Any) {
+        if let Some(ref hook) = self.on_panic {
+            hook(bot, update, route, panic_message(payload))
+        }
+    }
+
+    // Report an update that can't be decoded, nothing is done without a hook
+    pub fn invalid_update(&self, bot: &AwesomeBot, invalid: &InvalidUpdate) {
+        if let Some(ref hook) = self.on_invalid_update {
+            hook(bot, invalid)
+        }
+    }
+}
+
+// The message given to `panic!`, if there is one
+fn panic_message(payload: &Any) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "Box<Any>"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HandlerResult;
+    use std::sync::{Arc, Mutex};
+    use test::fixtures::{test_bot, update};
+
+    #[test]
+    fn handler_result_right() {
+        assert!(().into_result().is_ok());
+        assert!(Ok::<(), String>(()).into_result().is_ok());
+        let e = Err::<(), &str>("failed").into_result().unwrap_err();
+        assert_eq!(e.to_string(), "failed");
+    }
+
+    #[test]
+    fn hooks_right() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut bot = test_bot();
+        bot.callback_query_fn(|_, _| Err::<(), _>("failed"));
+        bot.callback("^p$", |_, _, _, _| -> () { panic!("oops") });
+        let errors = log.clone();
+        bot.on_error(move |_, update, route, e| {
+            let entry = format!("{} {}: {}", update.update_id, route, e);
+            errors.lock().unwrap().push(entry)
+        });
+        let panics = log.clone();
+        bot.on_panic(move |_, update, route, message| {
+            let entry = format!("{} {}: {}", update.update_id, route, message);
+            panics.lock().unwrap().push(entry)
+        });
+
+        // The callback queries are reported with their update too
+        bot.handle_update(update(
+            r#"{"update_id": 7, "callback_query": {"id": "q", "data": "p",
+                "from": {"id": 2, "first_name": "A"}}}"#,
+        ));
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["7 #0 callback_query_fn: failed", "7 #1 callback(^p$): oops"]
+        );
+    }
+}
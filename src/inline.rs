@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+
+use rustc_serialize::json::{Json, ToJson};
+
+/// A result to answer an inline query, add them to an `AnswerInlineQuery` with the
+/// `article`, `photo` and `document` methods, or build them directly and use `result`.
+#[derive(Clone, Debug)]
+pub enum InlineQueryResult {
+    /// A text message.
+    Article {
+        id: String,
+        title: String,
+        /// Text of the message to send.
+        text: String,
+        description: Option<String>,
+        url: Option<String>,
+        thumb_url: Option<String>,
+    },
+    /// A photo, with the URL of a JPEG image.
+    Photo {
+        id: String,
+        photo_url: String,
+        thumb_url: String,
+        title: Option<String>,
+        caption: Option<String>,
+    },
+    /// A document, with the URL of a PDF or ZIP file.
+    Document {
+        id: String,
+        title: String,
+        document_url: String,
+        /// `application/pdf` or `application/zip`
+        mime_type: String,
+        description: Option<String>,
+        caption: Option<String>,
+    },
+}
+
+// Insert the value only if it's present
+fn insert_opt(obj: &mut BTreeMap<String, Json>, key: &str, value: &Option<String>) {
+    if let Some(ref v) = *value {
+        obj.insert(key.to_owned(), v.to_json());
+    }
+}
+
+impl ToJson for InlineQueryResult {
+    fn to_json(&self) -> Json {
+        let mut obj = BTreeMap::new();
+        match *self {
+            InlineQueryResult::Article {
+                ref id,
+                ref title,
+                ref text,
+                ref description,
+                ref url,
+                ref thumb_url,
+            } => {
+                let mut content = BTreeMap::new();
+                content.insert("message_text".to_owned(), text.to_json());
+                obj.insert("type".to_owned(), "article".to_json());
+                obj.insert("id".to_owned(), id.to_json());
+                obj.insert("title".to_owned(), title.to_json());
+                obj.insert("input_message_content".to_owned(), Json::Object(content));
+                insert_opt(&mut obj, "description", description);
+                insert_opt(&mut obj, "url", url);
+                insert_opt(&mut obj, "thumb_url", thumb_url);
+            }
+            InlineQueryResult::Photo {
+                ref id,
+                ref photo_url,
+                ref thumb_url,
+                ref title,
+                ref caption,
+            } => {
+                obj.insert("type".to_owned(), "photo".to_json());
+                obj.insert("id".to_owned(), id.to_json());
+                obj.insert("photo_url".to_owned(), photo_url.to_json());
+                obj.insert("thumb_url".to_owned(), thumb_url.to_json());
+                insert_opt(&mut obj, "title", title);
+                insert_opt(&mut obj, "caption", caption);
+            }
+            InlineQueryResult::Document {
+                ref id,
+                ref title,
+                ref document_url,
+                ref mime_type,
+                ref description,
+                ref caption,
+            } => {
+                obj.insert("type".to_owned(), "document".to_json());
+                obj.insert("id".to_owned(), id.to_json());
+                obj.insert("title".to_owned(), title.to_json());
+                obj.insert("document_url".to_owned(), document_url.to_json());
+                obj.insert("mime_type".to_owned(), mime_type.to_json());
+                insert_opt(&mut obj, "description", description);
+                insert_opt(&mut obj, "caption", caption);
+            }
+        }
+        Json::Object(obj)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::InlineQueryResult;
+    use rustc_serialize::json::ToJson;
+
+    #[test]
+    fn inline_article_json_right() {
+        let article = InlineQueryResult::Article {
+            id: String::from("1"),
+            title: String::from("Title"),
+            text: String::from("Hello"),
+            description: None,
+            url: None,
+            thumb_url: None,
+        };
+        assert_eq!(
+            article.to_json().to_string(),
+            "{\"id\":\"1\",\"input_message_content\":{\"message_text\":\"Hello\"},\
+             \"title\":\"Title\",\"type\":\"article\"}"
+        );
+    }
+}
@@ -0,0 +1,343 @@
+use std::collections::BTreeMap;
+
+use error::KeyboardError;
+use rustc_serialize::json::{self, Json, ToJson};
+use telegram_bot::{ReplyKeyboardMarkup, ReplyMarkup};
+
+/// A button of a `Keyboard`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Button {
+    /// Button that sends its text, in inline keyboards the text is also the callback data.
+    Text(String),
+    /// Inline button that opens an URL: (text, url).
+    Url(String, String),
+    /// Inline button that sends a callback query with the data: (text, data).
+    Callback(String, String),
+    /// Inline button that asks the user to choose a chat and starts an inline query
+    /// there: (text, query).
+    SwitchInline(String, String),
+    /// Inline button that starts an inline query in the current chat: (text, query).
+    SwitchInlineCurrent(String, String),
+}
+
+impl Button {
+    /// The text shown in the button.
+    pub fn text(&self) -> &str {
+        match *self {
+            Button::Text(ref t)
+            | Button::Url(ref t, _)
+            | Button::Callback(ref t, _)
+            | Button::SwitchInline(ref t, _)
+            | Button::SwitchInlineCurrent(ref t, _) => t,
+        }
+    }
+
+    /// Returns `true` if the button can only be used in inline keyboards.
+    pub fn is_inline(&self) -> bool {
+        match *self {
+            Button::Text(_) => false,
+            _ => true,
+        }
+    }
+
+    fn json(&self, inline: bool) -> Json {
+        let mut obj = BTreeMap::new();
+        let (text, extra) = match *self {
+            Button::Text(ref t) if inline => (t, Some(("callback_data", t))),
+            Button::Text(ref t) => (t, None),
+            Button::Url(ref t, ref u) => (t, Some(("url", u))),
+            Button::Callback(ref t, ref d) => (t, Some(("callback_data", d))),
+            Button::SwitchInline(ref t, ref q) => (t, Some(("switch_inline_query", q))),
+            Button::SwitchInlineCurrent(ref t, ref q) => {
+                (t, Some(("switch_inline_query_current_chat", q)))
+            }
+        };
+        obj.insert("text".to_owned(), text.to_json());
+        if let Some((key, value)) = extra {
+            obj.insert(key.to_owned(), value.to_json());
+        }
+        Json::Object(obj)
+    }
+}
+
+/// A reply or inline keyboard, build it with `KeyboardBuilder` and send it
+/// with the `keyboard` method of any send type.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keyboard {
+    inline: bool,
+    rows: Vec<Vec<Button>>,
+    one_time: Option<bool>,
+    resize: Option<bool>,
+    selective: Option<bool>,
+}
+
+impl Keyboard {
+    /// Returns `true` if it's an inline keyboard, the one shown below the message.
+    pub fn is_inline(&self) -> bool {
+        self.inline
+    }
+}
+
+impl ToJson for Keyboard {
+    fn to_json(&self) -> Json {
+        let rows = Json::Array(
+            self.rows
+                .iter()
+                .map(|row| Json::Array(row.iter().map(|b| b.json(self.inline)).collect()))
+                .collect(),
+        );
+
+        let mut obj = BTreeMap::new();
+        if self.inline {
+            obj.insert("inline_keyboard".to_owned(), rows);
+        } else {
+            obj.insert("keyboard".to_owned(), rows);
+            let flags = [
+                ("one_time_keyboard", self.one_time),
+                ("resize_keyboard", self.resize),
+                ("selective", self.selective),
+            ];
+            for &(key, value) in &flags {
+                if let Some(v) = value {
+                    obj.insert(key.to_owned(), v.to_json());
+                }
+            }
+        }
+        Json::Object(obj)
+    }
+}
+
+/// Builder of reply and inline keyboards.
+///
+/// The buttons are added to the current row, use `row` to start a new one, or `columns`
+/// to distribute all the buttons in rows of the same size.
+///
+/// ``` ignore
+/// let kb = KeyboardBuilder::inline()
+///     .callback("Yes", "answer:yes")
+///     .callback("No", "answer:no")
+///     .row()
+///     .url("More info", "https://telegram.org")
+///     .build();
+/// bot.answer(msg).text("Do you like it?").keyboard(kb).end();
+/// ```
+#[derive(Clone, Debug)]
+pub struct KeyboardBuilder {
+    keyboard: Keyboard,
+    columns: Option<usize>,
+}
+
+impl KeyboardBuilder {
+    fn new(inline: bool) -> KeyboardBuilder {
+        KeyboardBuilder {
+            keyboard: Keyboard {
+                inline: inline,
+                rows: vec![Vec::new()],
+                one_time: None,
+                resize: None,
+                selective: None,
+            },
+            columns: None,
+        }
+    }
+
+    /// Start a reply keyboard, the one that replaces the user's keyboard.
+    pub fn reply() -> KeyboardBuilder {
+        KeyboardBuilder::new(false)
+    }
+
+    /// Start an inline keyboard, the one that is shown below the message.
+    pub fn inline() -> KeyboardBuilder {
+        KeyboardBuilder::new(true)
+    }
+
+    /// Add a button to the current row.
+    pub fn add(&mut self, b: Button) -> &mut KeyboardBuilder {
+        if let Some(row) = self.keyboard.rows.last_mut() {
+            row.push(b);
+        }
+        self
+    }
+
+    /// Add a text button, in an inline keyboard the text is also the callback data.
+    pub fn button(&mut self, text: &str) -> &mut KeyboardBuilder {
+        self.add(Button::Text(text.to_owned()))
+    }
+
+    /// Add an inline button that opens the URL.
+    pub fn url(&mut self, text: &str, url: &str) -> &mut KeyboardBuilder {
+        self.add(Button::Url(text.to_owned(), url.to_owned()))
+    }
+
+    /// Add an inline button that sends a callback query with `data`.
+    pub fn callback(&mut self, text: &str, data: &str) -> &mut KeyboardBuilder {
+        self.add(Button::Callback(text.to_owned(), data.to_owned()))
+    }
+
+    /// Add an inline button that starts an inline query with `query` in the chat
+    /// that the user chooses.
+    pub fn switch_inline(&mut self, text: &str, query: &str) -> &mut KeyboardBuilder {
+        self.add(Button::SwitchInline(text.to_owned(), query.to_owned()))
+    }
+
+    /// Add an inline button that starts an inline query with `query` in the current chat.
+    pub fn switch_inline_current(&mut self, text: &str, query: &str) -> &mut KeyboardBuilder {
+        self.add(Button::SwitchInlineCurrent(text.to_owned(), query.to_owned()))
+    }
+
+    /// Start a new row of buttons.
+    pub fn row(&mut self) -> &mut KeyboardBuilder {
+        if self.keyboard.rows.last().map_or(false, |r| !r.is_empty()) {
+            self.keyboard.rows.push(Vec::new());
+        }
+        self
+    }
+
+    /// Distribute all the buttons in rows of `n` buttons, ignoring the rows added.
+    pub fn columns(&mut self, n: usize) -> &mut KeyboardBuilder {
+        self.columns = if n > 0 { Some(n) } else { None };
+        self
+    }
+
+    /// Hide the reply keyboard after using it.
+    pub fn one_time(&mut self, o: bool) -> &mut KeyboardBuilder {
+        self.keyboard.one_time = Some(o);
+        self
+    }
+
+    /// Resize the reply keyboard to fit the buttons.
+    pub fn resize(&mut self, r: bool) -> &mut KeyboardBuilder {
+        self.keyboard.resize = Some(r);
+        self
+    }
+
+    /// Show the reply keyboard only to the mentioned users or the sender of the replied message.
+    pub fn selective(&mut self, s: bool) -> &mut KeyboardBuilder {
+        self.keyboard.selective = Some(s);
+        self
+    }
+
+    /// Build the keyboard. It panics if it's a reply keyboard with inline buttons
+    /// (`url`, `callback`, ...), use `try_build` to get the error instead.
+    pub fn build(&self) -> Keyboard {
+        match self.try_build() {
+            Ok(keyboard) => keyboard,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Build the keyboard, returning an error if it's a reply keyboard with inline buttons.
+    pub fn try_build(&self) -> Result<Keyboard, KeyboardError> {
+        if !self.keyboard.inline {
+            let inline = self.keyboard.rows.iter().flat_map(|r| r.iter()).find(|b| b.is_inline());
+            if let Some(b) = inline {
+                return Err(KeyboardError { button: b.clone() });
+            }
+        }
+
+        let mut keyboard = self.keyboard.clone();
+        keyboard.rows.retain(|r| !r.is_empty());
+        if let Some(n) = self.columns {
+            let buttons: Vec<Button> = keyboard.rows.concat();
+            keyboard.rows = buttons.chunks(n).map(|c| c.to_vec()).collect();
+        }
+        Ok(keyboard)
+    }
+}
+
+/// The markup sent with a message, created with the `keyboard`, `hide` and `force` methods
+/// of the send types.
+#[derive(Clone, Debug)]
+pub enum Markup {
+    /// A markup of `telegram-bot`.
+    Reply(ReplyMarkup),
+    /// A keyboard created with `KeyboardBuilder`.
+    Keyboard(Keyboard),
+}
+
+impl From<ReplyMarkup> for Markup {
+    fn from(r: ReplyMarkup) -> Markup {
+        Markup::Reply(r)
+    }
+}
+
+impl From<ReplyKeyboardMarkup> for Markup {
+    fn from(r: ReplyKeyboardMarkup) -> Markup {
+        Markup::Reply(ReplyMarkup::from(r))
+    }
+}
+
+impl From<Keyboard> for Markup {
+    fn from(k: Keyboard) -> Markup {
+        Markup::Keyboard(k)
+    }
+}
+
+impl ToJson for Markup {
+    fn to_json(&self) -> Json {
+        match *self {
+            Markup::Reply(ref r) => json::encode(r)
+                .ok()
+                .and_then(|s| Json::from_str(&s).ok())
+                .unwrap_or(Json::Null),
+            Markup::Keyboard(ref k) => k.to_json(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Button, KeyboardBuilder};
+    use rustc_serialize::json::ToJson;
+
+    #[test]
+    fn keyboard_reply_json_right() {
+        let kb = KeyboardBuilder::reply()
+            .button("a")
+            .button("b")
+            .row()
+            .button("c")
+            .one_time(true)
+            .build();
+        assert_eq!(
+            kb.to_json().to_string(),
+            "{\"keyboard\":[[{\"text\":\"a\"},{\"text\":\"b\"}],[{\"text\":\"c\"}]],\
+             \"one_time_keyboard\":true}"
+        );
+    }
+
+    #[test]
+    fn keyboard_inline_columns_right() {
+        let kb = KeyboardBuilder::inline()
+            .callback("1", "one")
+            .url("2", "http://two")
+            .row()
+            .switch_inline("3", "three")
+            .columns(2)
+            .build();
+        assert_eq!(
+            kb.to_json().to_string(),
+            "{\"inline_keyboard\":[[{\"callback_data\":\"one\",\"text\":\"1\"},\
+             {\"text\":\"2\",\"url\":\"http://two\"}],\
+             [{\"switch_inline_query\":\"three\",\"text\":\"3\"}]]}"
+        );
+    }
+
+    #[test]
+    fn keyboard_reply_inline_buttons_wrong() {
+        let err = KeyboardBuilder::reply()
+            .button("a")
+            .url("b", "http://b")
+            .callback("c", "c")
+            .try_build()
+            .unwrap_err();
+        assert_eq!(err.button, Button::Url(String::from("b"), String::from("http://b")));
+        assert!(KeyboardBuilder::inline().button("a").url("b", "http://b").try_build().is_ok());
+    }
+
+    #[test]
+    #[should_panic]
+    fn keyboard_reply_callback_panic_wrong() {
+        KeyboardBuilder::reply().callback("a", "a").build();
+    }
+}
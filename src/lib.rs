@@ -1,5 +1,5 @@
 //! This crate helps writing bots for Telegram. This is a framework to build the bots,
-//! the main wrapped crate is `telegram-bot` which provides the actual protocol access.
+//! the main wrapped crate is `telegram-bot` which provides the types of the Bot API.
 //!
 //! How to use it
 //! -------------
@@ -10,6 +10,8 @@
 //!
 //! You have two ways to create the bot, with `new`,
 //! that you pass the bot token directly, or with `from_env`, more recommended.
+//! If you want to change the default options (threads, polling timeout, API URL, ...),
+//! use the [`AwesomeBotBuilder`](struct.AwesomeBotBuilder.html).
 //!
 //! This framework uses the "route" pattern to apply behavior.
 //! There are plenty of routing ways available, the main ones are:
@@ -29,14 +31,27 @@
 //! Check [`SendBuilder`](struct.SendBuilder.html) struct implementation to see the methods
 //! available (text, photo, audio, ...)
 //!
-//! Once you have all your routings, you need to start the bot, right now it supports only
-//! getUpdates method, just call the `simple_start` method in AwesomeBot.
+//! The sends respect Telegram's rate limits, `end` waits if the bot is sending too fast
+//! and returns the answer, while `enqueue` sends it in the background without waiting.
+//!
+//! The messages already sent can be modified the same way, with an
+//! [`EditBuilder`](struct.EditBuilder.html) created by `edit` or `edit_message`.
+//!
+//! Once you have all your routings, you need to start the bot, you have two ways:
+//!
+//! - `simple_start`: Uses the `getUpdates` method (long polling), the easiest one.
+//! - `webhook_start`: Starts an HTTP server that receives the updates that Telegram
+//!   POSTs to your webhook, useful if you are behind a reverse proxy.
 //!
 //! You don't have to worry about blocking the bot in a function handler,
-//! because it uses a thread pool (of 4 threads right now,
-//! it will be configurable in the future), so the handling for
+//! because it uses a thread pool (of 4 threads by default, you can change it with
+//! the `pool_size` option of the builder), so the handling for
 //! a message is done in his own thread.
 //!
+//! To stop the bot, get a [`ShutdownHandle`](struct.ShutdownHandle.html) with `shutdown_handle`
+//! before starting it, and call `shutdown` from any thread (or `shutdown_on_signals`
+//! to stop with Ctrl-C).
+//!
 //! # Examples
 //!
 //! ## Minimalistic example (Echo text)
@@ -72,23 +87,80 @@
 //! You have more examples in `examples/` directory in the project's repository.
 //!
 
+extern crate ctrlc;
+extern crate hyper;
 extern crate regex;
 extern crate rustc_serialize;
-extern crate scoped_threadpool;
 extern crate telegram_bot;
+extern crate threadpool;
 
+// First, its macros are used in the other modules
+#[macro_use]
 mod send;
+mod api;
+mod args;
+mod builder;
+mod dialog;
+mod dispatch;
+mod edit;
+mod error;
+mod file;
+mod filter;
+mod handler;
+mod inline;
+mod keyboard;
+mod middleware;
+mod offset;
+mod queue;
+mod reply;
+mod retry;
+mod session;
+mod shutdown;
 mod test;
-
+mod update;
+mod webhook;
+
+pub use args::CommandArgs;
+pub use builder::*;
+pub use dialog::Dialog;
+pub use dispatch::DispatchPolicy;
+pub use edit::*;
+pub use error::*;
+pub use file::{FileInfo, FileSource, InputFile};
+pub use handler::{HandlerError, HandlerResult, RouteId};
+pub use inline::InlineQueryResult;
+pub use keyboard::{Button, Keyboard, KeyboardBuilder, Markup};
+pub use middleware::{Flow, Middleware};
+pub use offset::{FileOffsetStore, MemoryOffsetStore, OffsetStore};
+pub use queue::RateLimits;
+pub use retry::{is_transient, RetryPolicy};
 pub use send::*;
+pub use session::{JsonFileSessionStore, MemorySessionStore, Session, SessionStore};
+pub use shutdown::*;
+pub use update::{CallbackQuery, ChosenInlineResult, InlineQuery, InvalidUpdate, Update};
 
 pub use telegram_bot::*;
 
-use scoped_threadpool::Pool;
+use threadpool::ThreadPool;
 
+use args::split_args;
+use dialog::Dialogs;
+use filter::{update_parts, Filter};
+use handler::Hooks;
+use offset::OffsetTracker;
+use reply::Replies;
 use regex::Regex;
+use std::cmp;
 use std::env;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 /// Represents audio and voice, this is used in `all_music_fn` handler.
 pub enum GeneralSound {
@@ -96,101 +168,212 @@ pub enum GeneralSound {
     Voice(Voice),
 }
 
+// What the handlers return once converted
+type HandlerOutcome = std::result::Result<(), HandlerError>;
+
 // This enumeration determines what type of routing handler to use
 #[derive(Clone)]
 enum Muxer {
     PatternMux(
         Regex,
-        Arc<Fn(&AwesomeBot, &Message, String, Vec<String>) + Send + Sync + 'static>,
+        Arc<Fn(&AwesomeBot, &Message, String, Vec<String>) -> HandlerOutcome + Send + Sync + 'static>,
     ),
     TextMux(
         Regex,
-        Arc<Fn(&AwesomeBot, &Message, String) + Send + Sync + 'static>,
+        Arc<Fn(&AwesomeBot, &Message, String) -> HandlerOutcome + Send + Sync + 'static>,
+    ),
+    PhotoMux(
+        Arc<Fn(&AwesomeBot, &Message, Vec<PhotoSize>) -> HandlerOutcome + Send + Sync + 'static>,
+    ),
+    VideoMux(Arc<Fn(&AwesomeBot, &Message, Video) -> HandlerOutcome + Send + Sync + 'static>),
+    DocumentMux(Arc<Fn(&AwesomeBot, &Message, Document) -> HandlerOutcome + Send + Sync + 'static>),
+    StickerMux(Arc<Fn(&AwesomeBot, &Message, Sticker) -> HandlerOutcome + Send + Sync + 'static>),
+    AudioMux(Arc<Fn(&AwesomeBot, &Message, Audio) -> HandlerOutcome + Send + Sync + 'static>),
+    VoiceMux(Arc<Fn(&AwesomeBot, &Message, Voice) -> HandlerOutcome + Send + Sync + 'static>),
+    GeneralAudioMux(
+        Arc<Fn(&AwesomeBot, &Message, GeneralSound) -> HandlerOutcome + Send + Sync + 'static>,
+    ),
+    ContactMux(Arc<Fn(&AwesomeBot, &Message, Contact) -> HandlerOutcome + Send + Sync + 'static>),
+    LocationMux(
+        Arc<Fn(&AwesomeBot, &Message, Float, Float) -> HandlerOutcome + Send + Sync + 'static>,
+    ),
+    NewParticipantMux(
+        Arc<Fn(&AwesomeBot, &Message, User) -> HandlerOutcome + Send + Sync + 'static>,
+    ),
+    LeftParticipantMux(
+        Arc<Fn(&AwesomeBot, &Message, User) -> HandlerOutcome + Send + Sync + 'static>,
+    ),
+    NewTitleMux(Arc<Fn(&AwesomeBot, &Message, String) -> HandlerOutcome + Send + Sync + 'static>),
+    NewChatPhotoMux(
+        Arc<Fn(&AwesomeBot, &Message, Vec<PhotoSize>) -> HandlerOutcome + Send + Sync + 'static>,
+    ),
+    DeleteChatPhotoMux(
+        Arc<Fn(&AwesomeBot, &Message, Chat) -> HandlerOutcome + Send + Sync + 'static>,
+    ),
+    GroupChatCreatedMux(
+        Arc<Fn(&AwesomeBot, &Message, Chat) -> HandlerOutcome + Send + Sync + 'static>,
     ),
-    PhotoMux(Arc<Fn(&AwesomeBot, &Message, Vec<PhotoSize>) + Send + Sync + 'static>),
-    VideoMux(Arc<Fn(&AwesomeBot, &Message, Video) + Send + Sync + 'static>),
-    DocumentMux(Arc<Fn(&AwesomeBot, &Message, Document) + Send + Sync + 'static>),
-    StickerMux(Arc<Fn(&AwesomeBot, &Message, Sticker) + Send + Sync + 'static>),
-    AudioMux(Arc<Fn(&AwesomeBot, &Message, Audio) + Send + Sync + 'static>),
-    VoiceMux(Arc<Fn(&AwesomeBot, &Message, Voice) + Send + Sync + 'static>),
-    GeneralAudioMux(Arc<Fn(&AwesomeBot, &Message, GeneralSound) + Send + Sync + 'static>),
-    ContactMux(Arc<Fn(&AwesomeBot, &Message, Contact) + Send + Sync + 'static>),
-    LocationMux(Arc<Fn(&AwesomeBot, &Message, Float, Float) + Send + Sync + 'static>),
-    NewParticipantMux(Arc<Fn(&AwesomeBot, &Message, User) + Send + Sync + 'static>),
-    LeftParticipantMux(Arc<Fn(&AwesomeBot, &Message, User) + Send + Sync + 'static>),
-    NewTitleMux(Arc<Fn(&AwesomeBot, &Message, String) + Send + Sync + 'static>),
-    NewChatPhotoMux(Arc<Fn(&AwesomeBot, &Message, Vec<PhotoSize>) + Send + Sync + 'static>),
-    DeleteChatPhotoMux(Arc<Fn(&AwesomeBot, &Message, Chat) + Send + Sync + 'static>),
-    GroupChatCreatedMux(Arc<Fn(&AwesomeBot, &Message, Chat) + Send + Sync + 'static>),
     SuperGroupChatCreatedMux(
-        Arc<Fn(&AwesomeBot, &Message, GroupToSuperGroupMigration) + Send + Sync + 'static>,
+        Arc<Fn(&AwesomeBot, &Message, GroupToSuperGroupMigration) -> HandlerOutcome + Send + Sync + 'static>,
+    ),
+    ChannelChatCreatedMux(
+        Arc<Fn(&AwesomeBot, &Message, Chat) -> HandlerOutcome + Send + Sync + 'static>,
+    ),
+    AnyMux(Arc<Fn(&AwesomeBot, &Message) -> HandlerOutcome + Send + Sync + 'static>),
+    UnhandledMux(Arc<Fn(&AwesomeBot, &Message) -> HandlerOutcome + Send + Sync + 'static>),
+    StateMux(
+        String,
+        Arc<Fn(&AwesomeBot, &Message) -> HandlerOutcome + Send + Sync + 'static>,
+    ),
+    CallbackMux(
+        Regex,
+        Arc<Fn(&AwesomeBot, &CallbackQuery, String, Vec<String>) -> HandlerOutcome + Send + Sync + 'static>,
+    ),
+    AnyCallbackMux(Arc<Fn(&AwesomeBot, &CallbackQuery) -> HandlerOutcome + Send + Sync + 'static>),
+    InlineQueryMux(
+        Regex,
+        Arc<Fn(&AwesomeBot, &InlineQuery, String, Vec<String>) -> HandlerOutcome + Send + Sync + 'static>,
+    ),
+    AnyInlineQueryMux(Arc<Fn(&AwesomeBot, &InlineQuery) -> HandlerOutcome + Send + Sync + 'static>),
+    ChosenInlineResultMux(
+        Arc<Fn(&AwesomeBot, &ChosenInlineResult) -> HandlerOutcome + Send + Sync + 'static>,
     ),
-    ChannelChatCreatedMux(Arc<Fn(&AwesomeBot, &Message, Chat) + Send + Sync + 'static>),
-    AnyMux(Arc<Fn(&AwesomeBot, &Message) + Send + Sync + 'static>),
+}
+
+impl Muxer {
+    // The kind of update that triggers the muxer by default
+    fn default_kind(&self) -> UpdateKind {
+        match *self {
+            Muxer::CallbackMux(_, _) | Muxer::AnyCallbackMux(_) => UpdateKind::CallbackQuery,
+            Muxer::InlineQueryMux(_, _) | Muxer::AnyInlineQueryMux(_) => UpdateKind::InlineQuery,
+            Muxer::ChosenInlineResultMux(_) => UpdateKind::ChosenInlineResult,
+            _ => UpdateKind::Message,
+        }
+    }
+
+    // The routes that receive every update of their kind: they always run,
+    // and they don't count as a match
+    fn is_any(&self) -> bool {
+        match *self {
+            Muxer::AnyMux(_) | Muxer::AnyCallbackMux(_) | Muxer::AnyInlineQueryMux(_) => true,
+            _ => false,
+        }
+    }
+
+    fn pattern(&self) -> Option<String> {
+        match *self {
+            Muxer::PatternMux(ref r, _)
+            | Muxer::TextMux(ref r, _)
+            | Muxer::CallbackMux(ref r, _)
+            | Muxer::InlineQueryMux(ref r, _) => Some(r.as_str().to_owned()),
+            _ => None,
+        }
+    }
+}
+
+// A muxer with the kinds of update that trigger it, its priority and its filters
+#[derive(Clone)]
+struct Route {
+    id: RouteId,
+    mux: Muxer,
+    kinds: Vec<UpdateKind>,
+    priority: i32,
+    filters: Vec<Filter>,
 }
 
 // This macro matches one muxer and executes a block while sending "Any" message :)
+// Only the routes registered for the kind of update being handled are matched,
+// following the dispatch policy. It returns if any route matched.
 // First: self
 // Second: msg to pass
-// Third: List of Patterns to match => Code block to execute for that Pattern
+// Third: List of Patterns to match => Code block to execute for that Pattern,
+//        that returns the result of the handler if the route matched
 macro_rules! muxer_match {
     ($_self: expr, $msg: expr, [$($pat:pat => $result: expr),*]) => {
-        for m in &$_self.muxers {
-            match m {
-                &Muxer::AnyMux(ref f) => {
-                    f($_self, &$msg);
-                },
-                $($pat => $result,)*
-                _ => {},
-            }
-        }
+        $_self.dispatch(|mux| match mux {
+            &Muxer::AnyMux(ref f) => Some(f($_self, &$msg)),
+            $($pat => $result,)*
+            _ => None,
+        })
     }
 }
 
 // This macro adds a muxer to the muxers vec
 // First: self
-// Second: handler (function to add)
-// Third: The Muxer enum type
-// Fourth: List of extra parameters, in order, first passed to the muxer
+// Second: name of the method that adds it
+// Third: handler (function to add)
+// Fourth: The Muxer enum type
+// Fifth: List of extra parameters, in order, first passed to the muxer
+// Sixth: The parameters of the handler, with their types
 macro_rules! add_muxer {
     ($_self: expr,
+     $name: expr,
      $handler: expr,
      $mux: expr,
-     [$($extra: expr),*]) => {
+     [$($extra: expr),*],
+     [$($arg: ident: $t: ty),*]) => {
         {
-            let fa = Arc::new($handler);
-            $_self.muxers.push($mux($($extra,)* fa.clone()));
+            let handler = $handler;
+            let fa = Arc::new(move |$($arg: $t),*| handler($($arg),*).into_result());
+            let mux = $mux($($extra,)* fa);
+            let id = RouteId {
+                index: $_self.muxers.len(),
+                method: $name,
+                pattern: mux.pattern(),
+            };
+            Arc::make_mut(&mut $_self.muxers).push(Route {
+                id: id,
+                kinds: vec![mux.default_kind()],
+                mux: mux,
+                priority: 0,
+                filters: Vec::new(),
+            });
+            $_self.last_route_ignored = false;
             $_self
         }
     }
 }
 
+// The update being handled, the copies of the bot given to the handlers have it
+struct Context {
+    update: Arc<Update>,
+    kind: UpdateKind,
+    // Set by `stop_propagation`
+    stopped: AtomicBool,
+}
+
 /// Main type for building the Telegram Bot.
 ///
-/// Create a new instance using `new` or `from_env`, add routing handlers and start the bot.
+/// Create a new instance using `new`, `from_env` or `AwesomeBotBuilder`,
+/// add routing handlers and start the bot.
+#[derive(Clone)]
 pub struct AwesomeBot {
-    bot: Api,
+    api: api::Client,
     /// The ID of the bot.
     pub id: Integer,
     /// The username of the bot.
     pub username: String,
-    muxers: Vec<Muxer>,
-}
-
-impl Clone for AwesomeBot {
-    fn clone(&self) -> AwesomeBot {
-        let b = self.bot.clone();
-        let mut v: Vec<Muxer> = Vec::new();
-        for m in &self.muxers {
-            v.push(m.clone());
-        }
-        AwesomeBot {
-            bot: b,
-            id: self.id,
-            username: self.username.clone(),
-            muxers: v,
-        }
-    }
+    options: StartOptions,
+    shutdown: ShutdownHandle,
+    dispatch: DispatchPolicy,
+    hooks: Hooks,
+    // Panic when a route is invalid
+    strict: bool,
+    // The invalid routes ignored, without strict mode
+    route_errors: Arc<Vec<Arc<RouteError>>>,
+    // The last route was ignored, so the modifiers chained to it don't change the previous one
+    last_route_ignored: bool,
+    dialogs: Arc<Dialogs>,
+    // Ends the dialogs, without the `/`
+    cancel_command: Option<String>,
+    sessions: Arc<SessionStore>,
+    // Handlers waiting in `wait_reply`
+    replies: Arc<Replies>,
+    // Shared, so the copies for each update are cheap
+    middlewares: Arc<Vec<Arc<Middleware>>>,
+    muxers: Arc<Vec<Route>>,
+    // Only in the copies given to the handlers
+    context: Option<Arc<Context>>,
 }
 
 // unsafe impl Send for AwesomeBot { }
@@ -204,57 +387,241 @@ impl AwesomeBot {
     /// valid Telegram Bot Token by calling `get_me`.
     /// It panics if the token is invalid.
     pub fn new(token: &str) -> AwesomeBot {
-        let bot = Api::from_token(token).unwrap();
-        match bot.get_me() {
-            Ok(user) => AwesomeBot {
-                bot: bot,
-                id: user.id,
-                username: user.username.unwrap_or("".to_string()),
-                muxers: Vec::new(),
-            },
-            Err(e) => panic!("Invalid token! ({})", e),
-        }
+        AwesomeBotBuilder::new(token).build()
     }
 
     /// Will receive the Bot Token from the environment variable `var` and call `new`.
     /// It panics if the environment variable can't be read or if the token is invalid.
     pub fn from_env(var: &str) -> AwesomeBot {
-        let token = env::var(var)
-            .ok()
-            .expect(&format!("Environment variable {} error.", var));
+        match Self::try_from_env(var) {
+            Ok(bot) => bot,
+            Err(e @ BotError::MissingEnvVar(_)) => panic!("{}", e),
+            Err(e) => panic!("Invalid token! ({})", e),
+        }
+    }
+
+    /// Creates a new bot with the given token, the same as `new`,
+    /// but returning a `BotError` instead of panicking.
+    pub fn try_new(token: &str) -> std::result::Result<AwesomeBot, BotError> {
+        AwesomeBotBuilder::new(token).try_build()
+    }
+
+    // A bot without routes and with the default options, the builder changes them after
+    pub(crate) fn with_api(mut api: api::Client, id: Integer, username: String) -> AwesomeBot {
+        let shutdown = ShutdownHandle::new();
+        api.stop_retries_on(shutdown.clone());
+        AwesomeBot {
+            api: api,
+            id: id,
+            username: username,
+            options: StartOptions::default(),
+            shutdown: shutdown,
+            dispatch: DispatchPolicy::default(),
+            hooks: Hooks::default(),
+            strict: false,
+            route_errors: Arc::new(Vec::new()),
+            last_route_ignored: false,
+            dialogs: Arc::new(Dialogs::new(Duration::from_secs(600))),
+            cancel_command: Some(String::from("cancel")),
+            sessions: Arc::new(MemorySessionStore::new()),
+            replies: Arc::new(Replies::new()),
+            middlewares: Arc::new(Vec::new()),
+            muxers: Arc::new(Vec::new()),
+            context: None,
+        }
+    }
 
-        Self::new(&token)
+    /// Will receive the Bot Token from the environment variable `var` and call `try_new`.
+    pub fn try_from_env(var: &str) -> std::result::Result<AwesomeBot, BotError> {
+        match env::var(var) {
+            Ok(token) => Self::try_new(&token),
+            Err(_) => Err(BotError::MissingEnvVar(var.to_owned())),
+        }
     }
 
     // Listener functions
 
     /// Start the bot using `getUpdates` method, calling the routes defined before calling this method.
+    ///
+    /// It uses the options given to the builder, or the default ones.
     pub fn simple_start(&self) -> Result<()> {
-        let mut listener = self.bot.listener(ListeningMethod::LongPoll(Some(20)));
-        let mut pool = Pool::new(4);
-        // let botcloned = Arc::new(self.clone());
-
-        pool.scoped(|scoped| {
-            // Handle updates
-            let result = listener.listen(|u| {
-                if let Some(m) = u.message {
-                    // let bot_instance = botcloned.clone();
-                    scoped.execute(move || {
-                        // bot_instance.handle_message(m);
-                        self.handle_message(m);
-                    });
+        self.start_with(self.options.clone())
+    }
+
+    /// Start the bot using `getUpdates` method with the given options.
+    ///
+    /// It runs until an error happens receiving the updates (after retrying it as the
+    /// `RetryPolicy` of the builder says), or until the bot is stopped
+    /// with its `ShutdownHandle`, in that case it returns `Ok(())`. The shutdown is noticed
+    /// when the poll running returns, so it can take up to `poll_timeout` seconds.
+    ///
+    /// The offset is saved in the `offset_store` when the handlers of each update finish.
+    /// The new updates are received while the handlers of the previous ones run, so
+    /// Telegram forgets the updates of a poll once the next poll is done: if the bot
+    /// crashes, the updates of the last poll not finished are received again after
+    /// restarting it, but the ones of older polls still running are lost.
+    pub fn start_with(&self, options: StartOptions) -> Result<()> {
+        let bot = Arc::new(self.clone());
+        let pool = ThreadPool::new(cmp::max(options.pool_size, 1));
+        let allowed: Vec<String> = options
+            .allowed_updates
+            .iter()
+            .map(|k| k.as_str().to_owned())
+            .collect();
+        let offset = match options.offset {
+            Some(o) => Some(o),
+            None => try!(options.offset_store.load().map_err(Error::Io)),
+        };
+        let tracker = Arc::new(OffsetTracker::new(options.offset_store.clone(), offset));
+        self.shutdown.reset();
+
+        // Handle updates
+        let result = 'listen: loop {
+            if self.shutdown.is_shutdown() {
+                break Ok(());
+            }
+            // Ask for the updates after the last one received, even if some are still
+            // being handled, so a slow handler doesn't keep the new updates waiting
+            let offset = tracker.received();
+            let updates = match self.api.get_updates(offset, options.poll_timeout, &allowed) {
+                Ok(updates) => updates,
+                // Stopped while retrying
+                Err(_) if self.shutdown.is_shutdown() => break Ok(()),
+                Err(e) => break Err(e),
+            };
+            for u in updates {
+                // The rest of updates will be received again in the next start
+                if self.shutdown.is_shutdown() {
+                    break 'listen Ok(());
+                }
+                let u = match u {
+                    Ok(u) => u,
+                    Err(invalid) => {
+                        // Confirmed as if it was handled, after reporting it once
+                        if let Some(id) = invalid.update_id {
+                            if tracker.start(id) {
+                                self.hooks.invalid_update(self, &invalid);
+                                tracker.finish(id);
+                            }
+                        }
+                        continue;
+                    }
+                };
+                let id = u.update_id;
+                if !tracker.start(id) {
+                    continue;
+                }
+                // The replies go to the handler waiting without a free thread, it may
+                // be the only one able to free it
+                if self.replies.is_waiting(&u) {
+                    let _ = panic::catch_unwind(AssertUnwindSafe(|| self.handle_update(u)));
+                    tracker.finish(id);
+                    continue;
+                }
+                let bot = bot.clone();
+                let tracker = tracker.clone();
+                pool.execute(move || {
+                    // The panics of the handlers are already caught, but the update
+                    // must be finished anyway
+                    let _ = panic::catch_unwind(AssertUnwindSafe(|| bot.handle_update(u)));
+                    tracker.finish(id);
+                });
+            }
+        };
+
+        Self::drain(&pool, options.drain_timeout);
+        // Only the updates completely handled are confirmed
+        if let Some(o) = tracker.committed() {
+            self.shutdown.set_offset(o);
+            if result.is_ok() {
+                // Tell Telegram that all the updates until this one are handled
+                try!(self.api.confirm_updates(o));
+            }
+        }
+        result
+    }
+
+    /// Start the bot receiving the updates through a webhook, calling the routes defined
+    /// before calling this method.
+    ///
+    /// This starts an HTTP server listening in `addr` (for example `"0.0.0.0:8443"`) that
+    /// accepts the updates POSTed to `path` (for example `"/telegram"`). If a `secret` is given,
+    /// the requests without the same `X-Telegram-Bot-Api-Secret-Token` header are rejected.
+    ///
+    /// The webhook itself is not registered, you have to point it to your server with
+    /// `setWebhook` (usually through your reverse proxy).
+    ///
+    /// It runs until the bot is stopped with its `ShutdownHandle`. Then the server stops,
+    /// answering the updates that arrive with an error so Telegram sends them again later,
+    /// the updates already accepted are handled, and the address is freed.
+    pub fn webhook_start(&self, addr: &str, path: &str, secret: Option<&str>) -> Result<()> {
+        let (tx, rx) = mpsc::channel();
+        let mut server = try!(webhook::listen(addr, path, secret, tx));
+        self.shutdown.reset();
+        let bot = Arc::new(self.clone());
+        let pool = ThreadPool::new(cmp::max(self.options.pool_size, 1));
+        let tracker = Arc::new(OffsetTracker::new(self.options.offset_store.clone(), None));
+
+        // Handle updates, checking from time to time if we have to stop
+        loop {
+            if self.shutdown.is_shutdown() {
+                // No more updates are accepted, the ones waiting are handled below
+                server.close();
+            }
+            let u = match rx.recv_timeout(Duration::from_millis(200)) {
+                Ok(Ok(u)) => u,
+                Ok(Err(invalid)) => {
+                    self.hooks.invalid_update(self, &invalid);
+                    continue;
                 }
-                Ok(ListeningAction::Continue)
+                Err(RecvTimeoutError::Timeout) => continue,
+                // The server is closed and every update accepted was received
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            // Telegram doesn't send again the updates already answered, but with several
+            // connections they can arrive out of order, so all of them are handled
+            let id = u.update_id;
+            let _ = tracker.start(id);
+            if self.replies.is_waiting(&u) {
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| self.handle_update(u)));
+                tracker.finish(id);
+                continue;
+            }
+            let bot = bot.clone();
+            let tracker = tracker.clone();
+            pool.execute(move || {
+                // The panics of the handlers are already caught, but the update
+                // must be finished anyway
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| bot.handle_update(u)));
+                tracker.finish(id);
             });
-            scoped.join_all(); // Wait all scoped threads to finish
-            result
-        })
+        }
+
+        Self::drain(&pool, self.options.drain_timeout);
+        // Only the updates completely handled count
+        if let Some(o) = tracker.committed() {
+            self.shutdown.set_offset(o);
+        }
+        Ok(())
+    }
+
+    /// Get a handle to stop the bot, check `ShutdownHandle`.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    // Wait for the running handlers to finish, but not more than `timeout`
+    fn drain(pool: &ThreadPool, timeout: Duration) {
+        let start = Instant::now();
+        while pool.active_count() + pool.queued_count() > 0 && start.elapsed() < timeout {
+            thread::sleep(Duration::from_millis(50));
+        }
     }
 
     // Send builders
     /// Start a SendBuilder directly with the id, this is useful when you have the id saved and want to send a message.
     pub fn send(&self, id: Integer) -> SendBuilder {
-        SendBuilder::new(id, self.bot.clone())
+        SendBuilder::with_client(id, self.api.clone())
     }
 
     /// Start a SendBuilder answering a message directly, this is used to answer in a handler to the sender of the message.
@@ -262,8 +629,200 @@ impl AwesomeBot {
         self.send(m.chat.id())
     }
 
+    /// Start an EditBuilder to modify the message `message_id` of the chat `chat_id`.
+    pub fn edit(&self, chat_id: Integer, message_id: Integer) -> EditBuilder {
+        EditBuilder::new(chat_id, message_id, self.api.clone())
+    }
+
+    /// Start an EditBuilder to modify a message, for example, the one returned
+    /// by the `end` method when sending it.
+    ///
+    /// ``` ignore
+    /// if let Ok(sent) = bot.answer(msg).text("Loading...").end() {
+    ///     bot.edit_message(&sent).text("Done!").end();
+    /// }
+    /// ```
+    pub fn edit_message(&self, m: &Message) -> EditBuilder {
+        self.edit(m.chat.id(), m.message_id)
+    }
+
+    /// Start a DeleteMessage to delete the message `message_id` of the chat `chat_id`.
+    pub fn delete(&self, chat_id: Integer, message_id: Integer) -> DeleteMessage {
+        DeleteMessage::new(self.edit(chat_id, message_id))
+    }
+
+    // Download functions
+
+    /// Get the information of a file, with the path to download it.
+    pub fn get_file<F: FileSource + ?Sized>(&self, f: &F) -> Result<FileInfo> {
+        self.api.get_file(&try!(Self::source_id(f)))
+    }
+
+    /// Download a file received, for example, the `Document` of a `document_fn` handler,
+    /// or the `Vec<PhotoSize>` of `photo_fn` (the biggest size is downloaded).
+    ///
+    /// The files bigger than the `download_limit` of the builder (20 MB by default) fail.
+    /// The errors of the download itself are an `Error::Io` with a `DownloadError` inside.
+    pub fn download<F: FileSource + ?Sized>(&self, f: &F) -> Result<Vec<u8>> {
+        let mut content = Vec::new();
+        try!(self.download_into(f, &mut content));
+        Ok(content)
+    }
+
+    /// Download a file the same as `download`, but writing it to `out` as it's received.
+    /// Returns the number of bytes written.
+    ///
+    /// If it fails, part of the file may have been written already.
+    pub fn download_into<F: FileSource + ?Sized>(&self, f: &F, out: &mut io::Write) -> Result<u64> {
+        self.api.download(&try!(Self::source_id(f)), out)
+    }
+
+    /// Download a file the same as `download`, saving it in `path`.
+    /// Returns the number of bytes written.
+    ///
+    /// The file is written next to `path` (with `.part` added to the name) and renamed
+    /// when the download finishes, so `path` never has a partial file.
+    pub fn download_to<F, P>(&self, f: &F, path: P) -> Result<u64>
+    where
+        F: FileSource + ?Sized,
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".part");
+        let result = fs::File::create(&tmp)
+            .map_err(Error::Io)
+            .and_then(|mut file| self.download_into(f, &mut file))
+            .and_then(|written| fs::rename(&tmp, path).map(|_| written).map_err(Error::Io));
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        result
+    }
+
+    fn source_id<F: FileSource + ?Sized>(f: &F) -> Result<String> {
+        f.file_id().ok_or_else(|| DownloadError::NoFile.into_error())
+    }
+
+    /// The kind of the update that triggered the handler, useful in the handlers registered
+    /// for more than one kind with `update_kinds`.
+    pub fn update_kind(&self) -> UpdateKind {
+        self.context
+            .as_ref()
+            .map_or(UpdateKind::Message, |context| context.kind)
+    }
+
+    /// Don't run more routes for the update being handled, call it inside a handler.
+    /// It only works with `DispatchPolicy::Priority`.
+    pub fn stop_propagation(&self) {
+        if let Some(ref context) = self.context {
+            context.stopped.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Get the dialog with the sender of `msg` in its chat, to know or change its state.
+    /// Check `Dialog`.
+    pub fn dialog(&self, msg: &Message) -> Dialog {
+        Dialog::new(self.dialogs.clone(), msg)
+    }
+
+    /// Get the session of the sender of `msg` in its chat, to keep data between messages.
+    /// Check `Session`.
+    pub fn session(&self, msg: &Message) -> Session {
+        Session::new(self.sessions.clone(), msg)
+    }
+
+    /// Get the session of the update being handled, call it inside a handler. It's useful
+    /// in the handlers without message, like the ones of the callback and inline queries.
+    ///
+    /// It's the session of the sender in the chat of the message; for callback queries, of
+    /// the user that pressed the button in the chat of the message, and for inline queries,
+    /// of the user in its private chat with the bot. It's `None` outside the handlers.
+    pub fn update_session(&self) -> Option<Session> {
+        let context = match self.context {
+            Some(ref context) => context,
+            None => return None,
+        };
+        let (message, user) = update_parts(&context.update, context.kind);
+        match (message, user) {
+            // For the callback queries, the sender of the message is the bot
+            (Some(msg), Some(user)) if context.kind == UpdateKind::CallbackQuery => {
+                Some(Session::with_key(self.sessions.clone(), msg.chat.id(), user))
+            }
+            (Some(msg), _) => Some(Session::new(self.sessions.clone(), msg)),
+            // The private chat with the user has the same id
+            (None, Some(user)) => Some(Session::with_key(self.sessions.clone(), user, user)),
+            (None, None) => None,
+        }
+    }
+
+    /// Wait for the next message of the sender of `msg` in its chat, call it inside a handler.
+    /// It blocks the thread of the handler until the message arrives, or returns `None`
+    /// after `timeout`. The message is given only to this handler, the routes don't receive it.
+    /// It goes through the middlewares first, if one of them stops it, the handler keeps
+    /// waiting.
+    ///
+    /// If another handler is waiting for the same user in the same chat, that one stops
+    /// waiting and gets `None`.
+    ///
+    /// ``` ignore
+    /// fn ask(bot: &AwesomeBot, msg: &Message, _: String) {
+    ///     bot.answer(msg).text("What's your name?").end();
+    ///     match bot.wait_reply(msg, Duration::from_secs(60)) {
+    ///         Some(reply) => { /* ... */ }
+    ///         None => { bot.answer(msg).text("Too late!").end(); }
+    ///     }
+    /// }
+    /// ```
+    pub fn wait_reply(&self, msg: &Message, timeout: Duration) -> Option<Message> {
+        self.replies.wait((msg.chat.id(), msg.from.id), timeout)
+    }
+
+    /// Start an AnswerCallback builder to answer the `CallbackQuery` with that id.
+    pub fn answer_callback(&self, id: &str) -> AnswerCallback {
+        AnswerCallback::new(self.api.clone(), id)
+    }
+
+    /// Start an AnswerInlineQuery builder to answer the `InlineQuery` with that id.
+    pub fn answer_inline(&self, id: &str) -> AnswerInlineQuery {
+        AnswerInlineQuery::new(self.api.clone(), id)
+    }
+
     // AUXILIARY FUNCTIONS
 
+    // Compile the pattern of a route
+    fn compile(pattern: String) -> std::result::Result<Regex, RouteError> {
+        match Regex::new(&pattern) {
+            Ok(r) => Ok(r),
+            Err(e) => Err(RouteError {
+                pattern: pattern,
+                error: e,
+            }),
+        }
+    }
+
+    // Handle the error of a route added without `try_`, panicking in strict mode
+    // and keeping it for `route_errors` otherwise
+    fn check_route(&mut self, result: std::result::Result<(), RouteError>) -> &mut AwesomeBot {
+        if let Err(e) = result {
+            if self.strict {
+                panic!("{}", e);
+            }
+            Arc::make_mut(&mut self.route_errors).push(Arc::new(e));
+            self.last_route_ignored = true;
+        }
+        self
+    }
+
+    // The route changed by the modifiers (`priority`, `only_private`, ...), none if the
+    // last one was ignored
+    fn last_route(&mut self) -> Option<&mut Route> {
+        if self.last_route_ignored {
+            return None;
+        }
+        Arc::make_mut(&mut self.muxers).last_mut()
+    }
+
     // This function modifies the command by adding the username and some regex cleanup
     fn modify_command(orig: &str, username: &str) -> String {
         let s = String::from(orig);
@@ -298,145 +857,311 @@ impl AwesomeBot {
 
 // Internal handler functions
 impl AwesomeBot {
-    fn handle_text_msg(&self, msg: &Message, text: String) {
+    fn handle_text_msg(&self, msg: &Message, text: String) -> bool {
         use Muxer::*;
         muxer_match!(self, msg,
                      [&TextMux(ref r, ref f) =>
                       {
                          if r.is_match(&text) {
-                             f(self, msg, text.clone());
+                             Some(f(self, msg, text.clone()))
+                         } else {
+                             None
                          }
                       },
                       &PatternMux(ref r, ref f) =>
                       {
-                          if r.is_match(&text) { // If there are matches
-                              r.captures(&text) // Get the captures
-                                  .map(|c| { // Map over them because they are Option<_>
-                                      // Change the capture groups to Vec<String>
-                                      c.iter().map(|x| String::from(x.unwrap_or("")))
-                                          .collect::<Vec<_>>()
-                                  })
-                                  .map(|captures_vec|{
-                                      // If everything goes well, call the function
-                                      f(self, msg, text.clone(), captures_vec)
-                                  });
-                          }
+                          r.captures(&text) // Get the captures, if there are matches
+                              .map(|c| { // Map over them because they are Option<_>
+                                  // Change the capture groups to Vec<String>
+                                  c.iter().map(|x| String::from(x.unwrap_or("")))
+                                      .collect::<Vec<_>>()
+                              })
+                              .map(|captures_vec|{
+                                  // If everything goes well, call the function
+                                  f(self, msg, text.clone(), captures_vec)
+                              })
                       }]
-                     );
+                     )
     }
 
-    fn handle_image_msg(&self, msg: &Message, photos: Vec<PhotoSize>) {
+    fn handle_image_msg(&self, msg: &Message, photos: Vec<PhotoSize>) -> bool {
         use Muxer::*;
         muxer_match!(self, msg,
-                          [&PhotoMux(ref f) => f(self, msg, photos.clone())]
-                          );
+                          [&PhotoMux(ref f) => Some(f(self, msg, photos.clone()))]
+                          )
     }
 
-    fn handle_video_msg(&self, msg: &Message, video: Video) {
+    fn handle_video_msg(&self, msg: &Message, video: Video) -> bool {
         use Muxer::*;
         muxer_match!(self, msg,
-                     [&VideoMux(ref f) => f(self, msg, video.clone())]
-                     );
+                     [&VideoMux(ref f) => Some(f(self, msg, video.clone()))]
+                     )
     }
 
-    fn handle_document_msg(&self, msg: &Message, document: Document) {
+    fn handle_document_msg(&self, msg: &Message, document: Document) -> bool {
         use Muxer::*;
         muxer_match!(self, msg,
-                     [&DocumentMux(ref f) => f(self, msg, document.clone())]
-                     );
+                     [&DocumentMux(ref f) => Some(f(self, msg, document.clone()))]
+                     )
     }
 
-    fn handle_sticker_msg(&self, msg: &Message, sticker: Sticker) {
+    fn handle_sticker_msg(&self, msg: &Message, sticker: Sticker) -> bool {
         use Muxer::*;
         muxer_match!(self, msg,
-                     [&StickerMux(ref f) => f(self, msg, sticker.clone())]
-                     );
+                     [&StickerMux(ref f) => Some(f(self, msg, sticker.clone()))]
+                     )
     }
 
-    fn handle_audio_msg(&self, msg: &Message, audio: Audio) {
+    fn handle_audio_msg(&self, msg: &Message, audio: Audio) -> bool {
         use Muxer::*;
         muxer_match!(self, msg,
-                     [&AudioMux(ref f) => f(self, msg, audio.clone()),
-                      &GeneralAudioMux(ref f) => f(self, msg, GeneralSound::Audio(audio.clone()))]
-                          );
+                     [&AudioMux(ref f) => Some(f(self, msg, audio.clone())),
+                      &GeneralAudioMux(ref f) => Some(f(self, msg, GeneralSound::Audio(audio.clone())))]
+                          )
     }
 
-    fn handle_voice_msg(&self, msg: &Message, voice: Voice) {
+    fn handle_voice_msg(&self, msg: &Message, voice: Voice) -> bool {
         use Muxer::*;
         muxer_match!(self, msg,
-                          [&VoiceMux(ref f) => f(self, msg, voice.clone()),
-                           &GeneralAudioMux(ref f) => f(self, msg, GeneralSound::Voice(voice.clone()))]
-                          );
+                          [&VoiceMux(ref f) => Some(f(self, msg, voice.clone())),
+                           &GeneralAudioMux(ref f) => Some(f(self, msg, GeneralSound::Voice(voice.clone())))]
+                          )
     }
 
-    fn handle_contact_msg(&self, msg: &Message, cont: Contact) {
+    fn handle_contact_msg(&self, msg: &Message, cont: Contact) -> bool {
         use Muxer::*;
         muxer_match!(self, msg,
-                          [&ContactMux(ref f) => f(self, msg, cont.clone())]
-                          );
+                          [&ContactMux(ref f) => Some(f(self, msg, cont.clone()))]
+                          )
     }
 
-    fn handle_location_msg(&self, msg: &Message, f1: Float, f2: Float) {
+    fn handle_location_msg(&self, msg: &Message, f1: Float, f2: Float) -> bool {
         use Muxer::*;
         muxer_match!(self, msg,
-                          [&LocationMux(ref f) => f(self, msg, f1, f2)]
-                          );
+                          [&LocationMux(ref f) => Some(f(self, msg, f1, f2))]
+                          )
     }
 
-    fn handle_new_chat_msg(&self, msg: &Message, newp: User) {
+    fn handle_new_chat_msg(&self, msg: &Message, newp: User) -> bool {
         use Muxer::*;
         muxer_match!(self, msg,
-                          [&NewParticipantMux(ref f) => f(self, msg, newp.clone())]
-                          );
+                          [&NewParticipantMux(ref f) => Some(f(self, msg, newp.clone()))]
+                          )
     }
 
-    fn handle_left_part_msg(&self, msg: &Message, user: User) {
+    fn handle_left_part_msg(&self, msg: &Message, user: User) -> bool {
         use Muxer::*;
         muxer_match!(self, msg,
-                          [&LeftParticipantMux(ref f) => f(self, msg, user.clone())]
-                          );
+                          [&LeftParticipantMux(ref f) => Some(f(self, msg, user.clone()))]
+                          )
     }
 
-    fn handle_new_title_msg(&self, msg: &Message, title: String) {
+    fn handle_new_title_msg(&self, msg: &Message, title: String) -> bool {
         use Muxer::*;
         muxer_match!(self, msg,
-                          [&NewTitleMux(ref f) => f(self, msg, title.clone())]
-                          );
+                          [&NewTitleMux(ref f) => Some(f(self, msg, title.clone()))]
+                          )
     }
 
-    fn handle_chat_photo_msg(&self, msg: &Message, photos: Vec<PhotoSize>) {
+    fn handle_chat_photo_msg(&self, msg: &Message, photos: Vec<PhotoSize>) -> bool {
         use Muxer::*;
         muxer_match!(self, msg,
-                          [&NewChatPhotoMux(ref f) => f(self, msg, photos.clone())]
-                          );
+                          [&NewChatPhotoMux(ref f) => Some(f(self, msg, photos.clone()))]
+                          )
     }
 
-    fn handle_delete_photo_msg(&self, msg: &Message, group: Chat) {
+    fn handle_delete_photo_msg(&self, msg: &Message, group: Chat) -> bool {
         use Muxer::*;
         muxer_match!(self, msg,
-                          [&DeleteChatPhotoMux(ref f) => f(self, msg, group.clone())]
-                          );
+                          [&DeleteChatPhotoMux(ref f) => Some(f(self, msg, group.clone()))]
+                          )
     }
 
-    fn handle_group_created_msg(&self, msg: &Message, group: Chat) {
+    fn handle_group_created_msg(&self, msg: &Message, group: Chat) -> bool {
         use Muxer::*;
         muxer_match!(self, msg,
-                          [&GroupChatCreatedMux(ref f) => f(self, msg, group.clone())]
-                          );
+                          [&GroupChatCreatedMux(ref f) => Some(f(self, msg, group.clone()))]
+                          )
     }
 
     fn handle_super_group_chat_created_msg(
         &self,
         msg: &Message,
         migration: GroupToSuperGroupMigration,
-    ) {
+    ) -> bool {
+        use Muxer::*;
+        muxer_match!(self, msg, [&SuperGroupChatCreatedMux (ref f) => Some(f(self, msg, migration.clone()))])
+    }
+
+    fn handle_channel_chat_created_msg(&self, msg: &Message, chat: Chat) -> bool {
+        use Muxer::*;
+        muxer_match!(self, msg, [&ChannelChatCreatedMux (ref f) => Some(f(self, msg, chat.clone()))])
+    }
+
+    fn handle_callback_query(&self, query: CallbackQuery) {
+        use Muxer::*;
+        let data = query.data.clone().unwrap_or_default();
+        self.dispatch(|mux| match mux {
+            &AnyCallbackMux(ref f) => Some(f(self, &query)),
+            &CallbackMux(ref r, ref f) => r.captures(&data).map(|c| {
+                let captures_vec = c.iter()
+                    .map(|x| String::from(x.unwrap_or("")))
+                    .collect::<Vec<_>>();
+                f(self, &query, data.clone(), captures_vec)
+            }),
+            _ => None,
+        });
+    }
+
+    fn handle_inline_query(&self, query: InlineQuery) {
         use Muxer::*;
-        muxer_match!(self, msg, [&SuperGroupChatCreatedMux (ref f) => f(self, msg, migration.clone())]);
+        self.dispatch(|mux| match mux {
+            &AnyInlineQueryMux(ref f) => Some(f(self, &query)),
+            &InlineQueryMux(ref r, ref f) => r.captures(&query.query).map(|c| {
+                let captures_vec = c.iter()
+                    .map(|x| String::from(x.unwrap_or("")))
+                    .collect::<Vec<_>>();
+                f(self, &query, query.query.clone(), captures_vec)
+            }),
+            _ => None,
+        });
     }
 
-    fn handle_channel_chat_created_msg(&self, msg: &Message, chat: Chat) {
+    fn handle_chosen_inline_result(&self, chosen: ChosenInlineResult) {
         use Muxer::*;
-        muxer_match!(self, msg, [&ChannelChatCreatedMux (ref f) => f(self, msg, chat.clone())]);
+        self.dispatch(|mux| match mux {
+            &ChosenInlineResultMux(ref f) => Some(f(self, &chosen)),
+            _ => None,
+        });
+    }
+
+    // A copy of the bot to handle the part of the update of that kind
+    fn with_context(&self, update: &Arc<Update>, kind: UpdateKind) -> AwesomeBot {
+        let mut bot = self.clone();
+        bot.context = Some(Arc::new(Context {
+            update: update.clone(),
+            kind: kind,
+            stopped: AtomicBool::new(false),
+        }));
+        bot
+    }
+
+    // Call `fire` with the muxers of the routes for the kind of update being handled,
+    // following the dispatch policy. `fire` runs the handler if the muxer matches and
+    // returns its result. Returns if any route matched.
+    fn dispatch<F: FnMut(&Muxer) -> Option<HandlerOutcome>>(&self, mut fire: F) -> bool {
+        // Only the copies of `with_context` handle updates
+        let context = match self.context {
+            Some(ref context) => context,
+            None => return false,
+        };
+        let (message, user) = update_parts(&context.update, context.kind);
+        let mut routes: Vec<&Route> = self.muxers
+            .iter()
+            .filter(|r| r.kinds.contains(&context.kind))
+            .filter(|r| r.filters.iter().all(|f| f.check(message, user)))
+            .collect();
+        // Stable, so the same priority keeps the order they were added
+        routes.sort_by(|a, b| b.priority.cmp(&a.priority));
+
+        let mut matched = false;
+        let mut stopped = false;
+        for r in routes {
+            // The handlers of every update run even after stopping
+            if stopped && !r.mux.is_any() {
+                continue;
+            }
+            // A panic only stops the handler that panicked
+            let fired = match panic::catch_unwind(AssertUnwindSafe(|| fire(&r.mux))) {
+                Ok(Some(result)) => {
+                    if let Err(e) = result {
+                        self.hooks.error(self, &context.update, &r.id, &e);
+                    }
+                    // They run always, but they aren't a match
+                    !r.mux.is_any()
+                }
+                Ok(None) => false,
+                Err(payload) => {
+                    self.hooks.panic(self, &context.update, &r.id, &*payload);
+                    !r.mux.is_any()
+                }
+            };
+            matched = matched || fired;
+            stopped = stopped || match self.dispatch {
+                DispatchPolicy::All => false,
+                DispatchPolicy::First => fired,
+                DispatchPolicy::Priority => context.stopped.load(Ordering::SeqCst),
+            };
+        }
+        matched
+    }
+
+    // Is it the command that ends the dialogs?
+    fn is_cancel(&self, message: &Message) -> bool {
+        let command = match self.cancel_command {
+            Some(ref c) => c,
+            None => return false,
+        };
+        match message.msg {
+            MessageType::Text(ref t) => {
+                let t = t.trim();
+                t == format!("/{}", command) || t == format!("/{}@{}", command, self.username)
+            }
+            _ => false,
+        }
+    }
+
+    fn handle_update(&self, mut update: Update) {
+        // Number of middlewares whose `before` was called
+        let mut called = 0;
+        let mut flow = Flow::Continue;
+        for m in self.middlewares.iter() {
+            called += 1;
+            flow = m.before(self, &mut update);
+            if flow == Flow::Stop {
+                break;
+            }
+        }
+
+        let update = Arc::new(update);
+        // The replies go to the handler waiting for them, not to the routes
+        if flow == Flow::Continue && !self.replies.deliver(&update) {
+            self.route_update(&update);
+        }
+        for m in self.middlewares[..called].iter().rev() {
+            m.after(self, &update);
+        }
+    }
+
+    fn route_update(&self, update: &Arc<Update>) {
+        if let Some(ref message) = update.message {
+            self.with_context(update, UpdateKind::Message)
+                .handle_message(message.clone());
+        }
+        if let Some(ref message) = update.edited_message {
+            self.with_context(update, UpdateKind::EditedMessage)
+                .handle_message(message.clone());
+        }
+        if let Some(ref message) = update.channel_post {
+            self.with_context(update, UpdateKind::ChannelPost)
+                .handle_message(message.clone());
+        }
+        if let Some(ref message) = update.edited_channel_post {
+            self.with_context(update, UpdateKind::EditedChannelPost)
+                .handle_message(message.clone());
+        }
+        if let Some(ref query) = update.callback_query {
+            self.with_context(update, UpdateKind::CallbackQuery)
+                .handle_callback_query(query.clone());
+        }
+        if let Some(ref query) = update.inline_query {
+            self.with_context(update, UpdateKind::InlineQuery)
+                .handle_inline_query(query.clone());
+        }
+        if let Some(ref chosen) = update.chosen_inline_result {
+            self.with_context(update, UpdateKind::ChosenInlineResult)
+                .handle_chosen_inline_result(chosen.clone());
+        }
     }
 
     fn handle_message(&self, message: Message) {
@@ -449,8 +1174,30 @@ impl AwesomeBot {
         //     anybot.handle_any_msg(anym);
         // });
 
+        // The messages of a dialog go first to the handlers of its state
+        let dialog = self.dialog(&message);
+        if let Some(state) = dialog.state() {
+            let handled = if self.is_cancel(&message) {
+                dialog.end();
+                true
+            } else {
+                self.dispatch(|mux| match mux {
+                    &Muxer::StateMux(ref s, ref f) if *s == state => Some(f(self, &message)),
+                    _ => None,
+                })
+            };
+            if handled {
+                // The dialog took the message, the handlers of every message still get it
+                self.dispatch(|mux| match mux {
+                    &Muxer::AnyMux(ref f) => Some(f(self, &message)),
+                    _ => None,
+                });
+                return;
+            }
+        }
+
         // Rest of messages :)
-        match message.msg.clone() {
+        let matched = match message.msg.clone() {
             Text(text) => self.handle_text_msg(&message, text),
             Audio(audio) => self.handle_audio_msg(&message, audio),
             Voice(voice) => self.handle_voice_msg(&message, voice),
@@ -472,6 +1219,13 @@ impl AwesomeBot {
             ChannelChatCreated => {
                 self.handle_channel_chat_created_msg(&message, message.chat.clone())
             }
+        };
+
+        if !matched {
+            self.dispatch(|mux| match mux {
+                &Muxer::UnhandledMux(ref f) => Some(f(self, &message)),
+                _ => None,
+            });
         }
     }
 }
@@ -499,64 +1253,455 @@ impl AwesomeBot {
 ///    - `User`: An User is received when a participants leave or enter a group.
 ///    - `Chat::Group`: Whenever someone delete a chat photo, or create a group (add the bot to
 ///                     the group) you receive this.
+///    - `&CallbackQuery`: The callback handlers receive it instead of the `&Message`, it has
+///                        the data of the button and the message with the keyboard.
+///    - `&InlineQuery`, `&ChosenInlineResult`: The inline mode handlers receive them instead
+///                        of the `&Message`.
+///
+/// The handlers can return `()` or a `Result<(), E>` (check `HandlerResult`),
+/// the errors are given to the `on_error` hook.
 impl AwesomeBot {
     /// Add complex command routing (With capture groups).
     ///
     /// This method will transform the pattern to be exhaustive and include the mention to the bot,
     /// for example, the pattern `echo (.+)` will be used inside an the regular expression
     /// `^/start(?:@usernamebot)? (.+)$`
-    pub fn command<H>(&mut self, pattern: &str, handler: H) -> &mut AwesomeBot
+    pub fn command<H, R>(&mut self, pattern: &str, handler: H) -> &mut AwesomeBot
+    where
+        H: Fn(&AwesomeBot, &Message, String, Vec<String>) -> R + Send + Sync + 'static,
+        R: HandlerResult,
+    {
+        let result = self.try_command(pattern, handler).map(|_| ());
+        self.check_route(result)
+    }
+
+    /// The same as `command`, but returning an error if the pattern is invalid.
+    pub fn try_command<H, R>(
+        &mut self,
+        pattern: &str,
+        handler: H,
+    ) -> std::result::Result<&mut AwesomeBot, RouteError>
     where
-        H: Fn(&AwesomeBot, &Message, String, Vec<String>) + Send + Sync + 'static,
+        H: Fn(&AwesomeBot, &Message, String, Vec<String>) -> R + Send + Sync + 'static,
+        R: HandlerResult,
     {
         let nr = Self::modify_command(pattern, &self.username);
-        match Regex::new(&*nr) {
-            Ok(r) => add_muxer!(self, handler, Muxer::PatternMux, [r]),
-            Err(_) => self,
-        }
+        let r = try!(Self::compile(nr));
+        Ok(add_muxer!(self, "command", handler, Muxer::PatternMux, [r],
+                      [bot: &AwesomeBot, msg: &Message, a: String, b: Vec<String>]))
     }
 
     /// Add simple command routing (Without capture groups).
     ///
     /// This method will transform the pattern the same as `command` method, but the handler
     /// will not receive the capture groups.
-    pub fn simple_command<H>(&mut self, pattern: &str, handler: H) -> &mut AwesomeBot
+    pub fn simple_command<H, R>(&mut self, pattern: &str, handler: H) -> &mut AwesomeBot
+    where
+        H: Fn(&AwesomeBot, &Message, String) -> R + Send + Sync + 'static,
+        R: HandlerResult,
+    {
+        let result = self.try_simple_command(pattern, handler).map(|_| ());
+        self.check_route(result)
+    }
+
+    /// The same as `simple_command`, but returning an error if the pattern is invalid.
+    pub fn try_simple_command<H, R>(
+        &mut self,
+        pattern: &str,
+        handler: H,
+    ) -> std::result::Result<&mut AwesomeBot, RouteError>
     where
-        H: Fn(&AwesomeBot, &Message, String) + Send + Sync + 'static,
+        H: Fn(&AwesomeBot, &Message, String) -> R + Send + Sync + 'static,
+        R: HandlerResult,
     {
         let nr = Self::modify_command(pattern, &self.username);
-        match Regex::new(&*nr) {
-            Ok(r) => add_muxer!(self, handler, Muxer::TextMux, [r]),
-            Err(_) => self,
-        }
+        let r = try!(Self::compile(nr));
+        Ok(add_muxer!(self, "simple_command", handler, Muxer::TextMux, [r],
+                      [bot: &AwesomeBot, msg: &Message, a: String]))
+    }
+
+    /// Add a command routing whose arguments are parsed to the type `T`, check `CommandArgs`.
+    ///
+    /// If the arguments can't be parsed, the handler isn't called, and the bot answers with
+    /// the error and the `usage` text.
+    ///
+    /// ``` ignore
+    /// bot.typed_command("roll", "/roll <dice> <sides>", |bot, msg, (dice, sides): (u32, u32)| {
+    ///     bot.answer(msg).text(&format!("Rolling {}d{}", dice, sides)).end();
+    /// });
+    /// ```
+    pub fn typed_command<T, H, R>(&mut self, name: &str, usage: &str, handler: H) -> &mut AwesomeBot
+    where
+        T: CommandArgs + 'static,
+        H: Fn(&AwesomeBot, &Message, T) -> R + Send + Sync + 'static,
+        R: HandlerResult,
+    {
+        let result = self.try_typed_command(name, usage, handler).map(|_| ());
+        self.check_route(result)
+    }
+
+    /// The same as `typed_command`, but returning an error if its pattern is invalid.
+    pub fn try_typed_command<T, H, R>(
+        &mut self,
+        name: &str,
+        usage: &str,
+        handler: H,
+    ) -> std::result::Result<&mut AwesomeBot, RouteError>
+    where
+        T: CommandArgs + 'static,
+        H: Fn(&AwesomeBot, &Message, T) -> R + Send + Sync + 'static,
+        R: HandlerResult,
+    {
+        let usage = usage.to_owned();
+        let parser = move |bot: &AwesomeBot, msg: &Message, _: String, captures: Vec<String>| {
+            let words = split_args(captures.get(1).map_or("", |a| &a[..]));
+            match T::parse(&words) {
+                Ok(args) => handler(bot, msg, args).into_result(),
+                Err(e) => {
+                    let text = format!("{}\nUsage: {}", e, usage);
+                    try!(bot.answer(msg).text(&text).end());
+                    Ok(())
+                }
+            }
+        };
+
+        let nr = format!(
+            "^/{}(?:@{})?(?:\\s+([\\s\\S]*))?$",
+            regex::quote(name.trim_left_matches('/')),
+            self.username
+        );
+        let r = try!(Self::compile(nr));
+        Ok(add_muxer!(self, "typed_command", parser, Muxer::PatternMux, [r],
+                      [bot: &AwesomeBot, msg: &Message, a: String, b: Vec<String>]))
     }
 
     /// Add complex regular expression routing (With capture groups)
     ///
     /// This method won't tranform anything about the regular expression, you are free to write
     /// the expression you want and receive the capture groups matched.
-    pub fn regex<H>(&mut self, pattern: &str, handler: H) -> &mut AwesomeBot
+    pub fn regex<H, R>(&mut self, pattern: &str, handler: H) -> &mut AwesomeBot
     where
-        H: Fn(&AwesomeBot, &Message, String, Vec<String>) + Send + Sync + 'static,
+        H: Fn(&AwesomeBot, &Message, String, Vec<String>) -> R + Send + Sync + 'static,
+        R: HandlerResult,
     {
-        match Regex::new(pattern) {
-            Ok(r) => add_muxer!(self, handler, Muxer::PatternMux, [r]),
-            Err(_) => self,
-        }
+        let result = self.try_regex(pattern, handler).map(|_| ());
+        self.check_route(result)
+    }
+
+    /// The same as `regex`, but returning an error if the pattern is invalid.
+    pub fn try_regex<H, R>(
+        &mut self,
+        pattern: &str,
+        handler: H,
+    ) -> std::result::Result<&mut AwesomeBot, RouteError>
+    where
+        H: Fn(&AwesomeBot, &Message, String, Vec<String>) -> R + Send + Sync + 'static,
+        R: HandlerResult,
+    {
+        let r = try!(Self::compile(pattern.to_owned()));
+        Ok(add_muxer!(self, "regex", handler, Muxer::PatternMux, [r],
+                      [bot: &AwesomeBot, msg: &Message, a: String, b: Vec<String>]))
     }
 
     /// Add complex regular expression routing (Without capture groups)
     ///
     /// This method won't tranform anything about the regular expression, you are free to write
     /// the expression. The difference from `regex` is that you won't receive any capture groups.
-    pub fn simple_regex<H>(&mut self, pattern: &str, handler: H) -> &mut AwesomeBot
+    pub fn simple_regex<H, R>(&mut self, pattern: &str, handler: H) -> &mut AwesomeBot
+    where
+        H: Fn(&AwesomeBot, &Message, String) -> R + Send + Sync + 'static,
+        R: HandlerResult,
+    {
+        let result = self.try_simple_regex(pattern, handler).map(|_| ());
+        self.check_route(result)
+    }
+
+    /// The same as `simple_regex`, but returning an error if the pattern is invalid.
+    pub fn try_simple_regex<H, R>(
+        &mut self,
+        pattern: &str,
+        handler: H,
+    ) -> std::result::Result<&mut AwesomeBot, RouteError>
+    where
+        H: Fn(&AwesomeBot, &Message, String) -> R + Send + Sync + 'static,
+        R: HandlerResult,
+    {
+        let r = try!(Self::compile(pattern.to_owned()));
+        Ok(add_muxer!(self, "simple_regex", handler, Muxer::TextMux, [r],
+                      [bot: &AwesomeBot, msg: &Message, a: String]))
+    }
+
+    /// Add callback query routing (With capture groups).
+    ///
+    /// The regular expression is matched against the data of the button pressed, and the
+    /// handler receives the query, the data and the capture groups. The message with the
+    /// keyboard is in `query.message`.
+    ///
+    /// Remember to answer the query with `answer_callback`.
+    pub fn callback<H, R>(&mut self, pattern: &str, handler: H) -> &mut AwesomeBot
     where
-        H: Fn(&AwesomeBot, &Message, String) + Send + Sync + 'static,
+        H: Fn(&AwesomeBot, &CallbackQuery, String, Vec<String>) -> R + Send + Sync + 'static,
+        R: HandlerResult,
     {
-        match Regex::new(pattern) {
-            Ok(r) => add_muxer!(self, handler, Muxer::TextMux, [r]),
-            Err(_) => self,
+        let result = self.try_callback(pattern, handler).map(|_| ());
+        self.check_route(result)
+    }
+
+    /// The same as `callback`, but returning an error if the pattern is invalid.
+    pub fn try_callback<H, R>(
+        &mut self,
+        pattern: &str,
+        handler: H,
+    ) -> std::result::Result<&mut AwesomeBot, RouteError>
+    where
+        H: Fn(&AwesomeBot, &CallbackQuery, String, Vec<String>) -> R + Send + Sync + 'static,
+        R: HandlerResult,
+    {
+        let r = try!(Self::compile(pattern.to_owned()));
+        Ok(add_muxer!(self, "callback", handler, Muxer::CallbackMux, [r],
+                      [bot: &AwesomeBot, query: &CallbackQuery, a: String, b: Vec<String>]))
+    }
+
+    /// Add a routing handler that will be triggered on every callback query.
+    /// Like `any_fn`, it always runs, and it doesn't count as a match.
+    pub fn callback_query_fn<H, R>(&mut self, handler: H) -> &mut AwesomeBot
+    where
+        H: Fn(&AwesomeBot, &CallbackQuery) -> R + Send + Sync + 'static,
+        R: HandlerResult,
+    {
+        add_muxer!(self, "callback_query_fn", handler, Muxer::AnyCallbackMux, [],
+                   [bot: &AwesomeBot, query: &CallbackQuery])
+    }
+
+    /// Add inline query routing (With capture groups).
+    ///
+    /// The regular expression is matched against the text of the query, and the
+    /// handler receives the query, the text and the capture groups.
+    /// Answer it with `answer_inline`.
+    pub fn inline_query<H, R>(&mut self, pattern: &str, handler: H) -> &mut AwesomeBot
+    where
+        H: Fn(&AwesomeBot, &InlineQuery, String, Vec<String>) -> R + Send + Sync + 'static,
+        R: HandlerResult,
+    {
+        let result = self.try_inline_query(pattern, handler).map(|_| ());
+        self.check_route(result)
+    }
+
+    /// The same as `inline_query`, but returning an error if the pattern is invalid.
+    pub fn try_inline_query<H, R>(
+        &mut self,
+        pattern: &str,
+        handler: H,
+    ) -> std::result::Result<&mut AwesomeBot, RouteError>
+    where
+        H: Fn(&AwesomeBot, &InlineQuery, String, Vec<String>) -> R + Send + Sync + 'static,
+        R: HandlerResult,
+    {
+        let r = try!(Self::compile(pattern.to_owned()));
+        Ok(add_muxer!(self, "inline_query", handler, Muxer::InlineQueryMux, [r],
+                      [bot: &AwesomeBot, query: &InlineQuery, a: String, b: Vec<String>]))
+    }
+
+    /// Add a routing handler that will be triggered on every inline query.
+    /// Like `any_fn`, it always runs, and it doesn't count as a match.
+    pub fn inline_query_fn<H, R>(&mut self, handler: H) -> &mut AwesomeBot
+    where
+        H: Fn(&AwesomeBot, &InlineQuery) -> R + Send + Sync + 'static,
+        R: HandlerResult,
+    {
+        add_muxer!(self, "inline_query_fn", handler, Muxer::AnyInlineQueryMux, [],
+                   [bot: &AwesomeBot, query: &InlineQuery])
+    }
+
+    /// Add a routing handler that is triggered when a user chooses a result of an inline query.
+    pub fn chosen_inline_result_fn<H, R>(&mut self, handler: H) -> &mut AwesomeBot
+    where
+        H: Fn(&AwesomeBot, &ChosenInlineResult) -> R + Send + Sync + 'static,
+        R: HandlerResult,
+    {
+        add_muxer!(self, "chosen_inline_result_fn", handler, Muxer::ChosenInlineResultMux, [],
+                   [bot: &AwesomeBot, chosen: &ChosenInlineResult])
+    }
+
+    /// Set the kinds of update that trigger the last route added, by default the message
+    /// routes are only triggered by new messages (`UpdateKind::Message`).
+    ///
+    /// The valid kinds are `Message`, `EditedMessage`, `ChannelPost` and `EditedChannelPost`,
+    /// inside the handler, use `update_kind` to know which one triggered it.
+    ///
+    /// The channel posts don't have a sender, their `msg.from` is made up from the channel
+    /// (its id, and its title as `first_name`), so it isn't a real user.
+    ///
+    /// ``` ignore
+    /// bot.command("echo (.+)", echohandler)
+    ///     .update_kinds(&[UpdateKind::Message, UpdateKind::EditedMessage]);
+    /// ```
+    pub fn update_kinds(&mut self, kinds: &[UpdateKind]) -> &mut AwesomeBot {
+        if let Some(route) = self.last_route() {
+            route.kinds = kinds.to_vec();
+        }
+        self
+    }
+
+    /// Set the priority of the last route added, 0 by default. The routes with higher
+    /// priority are tried first.
+    ///
+    /// ``` ignore
+    /// bot.simple_command("start", start).priority(10);
+    /// bot.simple_regex(".*", fallback);
+    /// ```
+    pub fn priority(&mut self, priority: i32) -> &mut AwesomeBot {
+        if let Some(route) = self.last_route() {
+            route.priority = priority;
+        }
+        self
+    }
+
+    /// Only run the last route added in private chats.
+    ///
+    /// The filters can be combined, the route only runs if the update passes all of them.
+    /// They check the chat of the message, for callback queries the message with the button,
+    /// so they never pass with inline queries (only `from_users` works with them).
+    ///
+    /// ``` ignore
+    /// bot.simple_command("ban", ban).only_groups().from_users(&[ADMIN_ID]);
+    /// ```
+    pub fn only_private(&mut self) -> &mut AwesomeBot {
+        self.add_filter(Filter::Private)
+    }
+
+    /// Only run the last route added in groups and supergroups. Check `only_private`.
+    pub fn only_groups(&mut self) -> &mut AwesomeBot {
+        self.add_filter(Filter::Groups)
+    }
+
+    /// Only run the last route added with the updates of these users. Check `only_private`.
+    pub fn from_users(&mut self, ids: &[Integer]) -> &mut AwesomeBot {
+        self.add_filter(Filter::Users(ids.to_vec()))
+    }
+
+    /// Only run the last route added in these chats. Check `only_private`.
+    pub fn in_chats(&mut self, ids: &[Integer]) -> &mut AwesomeBot {
+        self.add_filter(Filter::Chats(ids.to_vec()))
+    }
+
+    /// Only run the last route added with the messages where `filter` returns `true`.
+    /// Check `only_private`.
+    ///
+    /// ``` ignore
+    /// bot.any_fn(log).custom(|msg| msg.forward.is_none());
+    /// ```
+    pub fn custom<F>(&mut self, filter: F) -> &mut AwesomeBot
+    where
+        F: Fn(&Message) -> bool + Send + Sync + 'static,
+    {
+        self.add_filter(Filter::Custom(Arc::new(filter)))
+    }
+
+    fn add_filter(&mut self, filter: Filter) -> &mut AwesomeBot {
+        if let Some(route) = self.last_route() {
+            route.filters.push(filter);
         }
+        self
+    }
+
+    /// Set `true` to panic when a route with an invalid pattern is added, instead of
+    /// ignoring it. Set it before adding the routes.
+    ///
+    /// To handle the errors yourself, use the `try_` methods (`try_command`, `try_regex`, ...).
+    pub fn strict(&mut self, s: bool) -> &mut AwesomeBot {
+        self.strict = s;
+        self
+    }
+
+    /// The errors of the routes ignored because their pattern is invalid, in the order
+    /// they were added. Check it after adding the routes if you don't use `strict`.
+    ///
+    /// The modifiers chained to an ignored route (`priority`, `only_private`, ...) do
+    /// nothing, they don't change the route added before it.
+    ///
+    /// ``` ignore
+    /// for e in bot.route_errors() {
+    ///     println!("{}", e);
+    /// }
+    /// ```
+    pub fn route_errors(&self) -> Vec<&RouteError> {
+        self.route_errors.iter().map(|e| &**e).collect()
+    }
+
+    /// Set how the routes that match an update are run, check `DispatchPolicy`.
+    pub fn dispatch_policy(&mut self, policy: DispatchPolicy) -> &mut AwesomeBot {
+        self.dispatch = policy;
+        self
+    }
+
+    /// Set the function called when a handler returns an error, it receives the update
+    /// being handled, the route of the handler and the error.
+    /// Without it, the errors are ignored, so set it to know when your handlers fail.
+    ///
+    /// ``` ignore
+    /// bot.on_error(|bot, update, route, e| {
+    ///     println!("Route {} failed: {}", route, e);
+    ///     if let Some(ref msg) = update.message {
+    ///         bot.answer(msg).text("Something went wrong :(").end();
+    ///     }
+    /// });
+    /// ```
+    pub fn on_error<F>(&mut self, hook: F) -> &mut AwesomeBot
+    where
+        F: Fn(&AwesomeBot, &Update, &RouteId, &HandlerError) + Send + Sync + 'static,
+    {
+        self.hooks.on_error = Some(Arc::new(hook));
+        self
+    }
+
+    /// Set the function called when a handler panics, it receives the update being handled,
+    /// the route of the handler and the panic message.
+    /// Without it, only the default panic hook of Rust prints the panic to stderr.
+    ///
+    /// The panic only stops that handler, the bot keeps handling the rest of routes and updates.
+    pub fn on_panic<F>(&mut self, hook: F) -> &mut AwesomeBot
+    where
+        F: Fn(&AwesomeBot, &Update, &RouteId, &str) + Send + Sync + 'static,
+    {
+        self.hooks.on_panic = Some(Arc::new(hook));
+        self
+    }
+
+    /// Set the function called when an update can't be decoded, usually because it has
+    /// something this version of the crate doesn't know yet. Those updates are confirmed
+    /// to Telegram like the rest and, without this hook, they are ignored.
+    ///
+    /// ``` ignore
+    /// bot.on_invalid_update(|_, invalid| {
+    ///     println!("Update {:?} ignored: {}", invalid.update_id, invalid.error);
+    /// });
+    /// ```
+    pub fn on_invalid_update<F>(&mut self, hook: F) -> &mut AwesomeBot
+    where
+        F: Fn(&AwesomeBot, &InvalidUpdate) + Send + Sync + 'static,
+    {
+        self.hooks.on_invalid_update = Some(Arc::new(hook));
+        self
+    }
+
+    /// Set the function called when a message sent in the background with `enqueue` fails,
+    /// it receives the Bot API method and the error. Without it, those errors are ignored.
+    ///
+    /// Set it before sending, the builders already created don't use it.
+    pub fn on_send_error<F>(&mut self, hook: F) -> &mut AwesomeBot
+    where
+        F: Fn(&str, &Error) + Send + Sync + 'static,
+    {
+        self.api.on_error(Arc::new(hook));
+        self
+    }
+
+    /// Add a middleware, that runs with every update before and after the routes.
+    /// They run in the order they are added, check `Middleware`.
+    pub fn middleware<M: Middleware + 'static>(&mut self, middleware: M) -> &mut AwesomeBot {
+        Arc::make_mut(&mut self.middlewares).push(Arc::new(middleware));
+        self
     }
 
     // pub fn multi_regex<H>(&mut self, patterns: Vec<&str>, handler: H) -> &mut AwesomeBot
@@ -565,156 +1710,231 @@ impl AwesomeBot {
     // }
 
     /// Add a routing handler that will be triggerer on every message, useful for logging.
-    pub fn any_fn<H>(&mut self, handler: H) -> &mut AwesomeBot
+    /// It receives the messages taken by the dialogs too.
+    pub fn any_fn<H, R>(&mut self, handler: H) -> &mut AwesomeBot
+    where
+        H: Fn(&AwesomeBot, &Message) -> R + Send + Sync + 'static,
+        R: HandlerResult,
+    {
+        add_muxer!(self, "any_fn", handler, Muxer::AnyMux, [],
+                   [bot: &AwesomeBot, msg: &Message])
+    }
+
+    /// Add a routing handler that is triggered only when no other route matched the message,
+    /// useful to answer with some help. The `any_fn` handlers don't count as a match.
+    ///
+    /// The type of the message is in `msg.msg`, so you can tell apart unknown commands
+    /// and unsupported media.
+    pub fn unhandled_fn<H, R>(&mut self, handler: H) -> &mut AwesomeBot
+    where
+        H: Fn(&AwesomeBot, &Message) -> R + Send + Sync + 'static,
+        R: HandlerResult,
+    {
+        add_muxer!(self, "unhandled_fn", handler, Muxer::UnhandledMux, [],
+                   [bot: &AwesomeBot, msg: &Message])
+    }
+
+    /// Add a routing handler for the messages of the dialogs in the state `state`,
+    /// they are handled before the rest of routes. Check `Dialog`.
+    ///
+    /// If the message isn't handled by any handler of the state, it goes to the normal routes.
+    pub fn state_fn<H, R>(&mut self, state: &str, handler: H) -> &mut AwesomeBot
     where
-        H: Fn(&AwesomeBot, &Message) + Send + Sync + 'static,
+        H: Fn(&AwesomeBot, &Message) -> R + Send + Sync + 'static,
+        R: HandlerResult,
     {
-        add_muxer!(self, handler, Muxer::AnyMux, [])
+        add_muxer!(self, "state_fn", handler, Muxer::StateMux, [state.to_owned()],
+                   [bot: &AwesomeBot, msg: &Message])
+    }
+
+    /// Set the time that a dialog keeps its state without changes, 10 minutes by default.
+    /// The dialogs already started keep their state, with the new timeout.
+    pub fn dialog_timeout(&mut self, timeout: Duration) -> &mut AwesomeBot {
+        self.dialogs.set_timeout(timeout);
+        self
+    }
+
+    /// Set the command that ends the dialogs, `/cancel` by default. The command is given
+    /// without the `/`, and `None` means that only the handlers end the dialogs.
+    ///
+    /// When it ends a dialog, the routes don't receive it (only the `any_fn` handlers),
+    /// outside of a dialog it's routed like any other command.
+    ///
+    /// ``` ignore
+    /// bot.cancel_command(Some("stop"));
+    /// ```
+    pub fn cancel_command(&mut self, command: Option<&str>) -> &mut AwesomeBot {
+        self.cancel_command = command.map(|c| c.trim_left_matches('/').to_owned());
+        self
     }
 
     /// Add a photo media routing handler.
-    pub fn photo_fn<H>(&mut self, handler: H) -> &mut AwesomeBot
+    pub fn photo_fn<H, R>(&mut self, handler: H) -> &mut AwesomeBot
     where
-        H: Fn(&AwesomeBot, &Message, Vec<PhotoSize>) + Send + Sync + 'static,
+        H: Fn(&AwesomeBot, &Message, Vec<PhotoSize>) -> R + Send + Sync + 'static,
+        R: HandlerResult,
     {
-        add_muxer!(self, handler, Muxer::PhotoMux, [])
+        add_muxer!(self, "photo_fn", handler, Muxer::PhotoMux, [],
+                   [bot: &AwesomeBot, msg: &Message, a: Vec<PhotoSize>])
     }
 
     /// Add a video media routing handler.
-    pub fn video_fn<H>(&mut self, handler: H) -> &mut AwesomeBot
+    pub fn video_fn<H, R>(&mut self, handler: H) -> &mut AwesomeBot
     where
-        H: Fn(&AwesomeBot, &Message, Video) + Send + Sync + 'static,
+        H: Fn(&AwesomeBot, &Message, Video) -> R + Send + Sync + 'static,
+        R: HandlerResult,
     {
-        add_muxer!(self, handler, Muxer::VideoMux, [])
+        add_muxer!(self, "video_fn", handler, Muxer::VideoMux, [],
+                   [bot: &AwesomeBot, msg: &Message, a: Video])
     }
 
     /// Add a document media routing handler.
-    pub fn document_fn<H>(&mut self, handler: H) -> &mut AwesomeBot
+    pub fn document_fn<H, R>(&mut self, handler: H) -> &mut AwesomeBot
     where
-        H: Fn(&AwesomeBot, &Message, Document) + Send + Sync + 'static,
+        H: Fn(&AwesomeBot, &Message, Document) -> R + Send + Sync + 'static,
+        R: HandlerResult,
     {
-        add_muxer!(self, handler, Muxer::DocumentMux, [])
+        add_muxer!(self, "document_fn", handler, Muxer::DocumentMux, [],
+                   [bot: &AwesomeBot, msg: &Message, a: Document])
     }
 
     /// Add a sticker media routing handler.
-    pub fn sticker_fn<H>(&mut self, handler: H) -> &mut AwesomeBot
+    pub fn sticker_fn<H, R>(&mut self, handler: H) -> &mut AwesomeBot
     where
-        H: Fn(&AwesomeBot, &Message, Sticker) + Send + Sync + 'static,
+        H: Fn(&AwesomeBot, &Message, Sticker) -> R + Send + Sync + 'static,
+        R: HandlerResult,
     {
-        add_muxer!(self, handler, Muxer::StickerMux, [])
+        add_muxer!(self, "sticker_fn", handler, Muxer::StickerMux, [],
+                   [bot: &AwesomeBot, msg: &Message, a: Sticker])
     }
 
     /// Add an audio media routing handler.
-    pub fn audio_fn<H>(&mut self, handler: H) -> &mut AwesomeBot
+    pub fn audio_fn<H, R>(&mut self, handler: H) -> &mut AwesomeBot
     where
-        H: Fn(&AwesomeBot, &Message, Audio) + Send + Sync + 'static,
+        H: Fn(&AwesomeBot, &Message, Audio) -> R + Send + Sync + 'static,
+        R: HandlerResult,
     {
-        add_muxer!(self, handler, Muxer::AudioMux, [])
+        add_muxer!(self, "audio_fn", handler, Muxer::AudioMux, [],
+                   [bot: &AwesomeBot, msg: &Message, a: Audio])
     }
 
     /// Add a voice media routing handler.
-    pub fn voice_fn<H>(&mut self, handler: H) -> &mut AwesomeBot
+    pub fn voice_fn<H, R>(&mut self, handler: H) -> &mut AwesomeBot
     where
-        H: Fn(&AwesomeBot, &Message, Voice) + Send + Sync + 'static,
+        H: Fn(&AwesomeBot, &Message, Voice) -> R + Send + Sync + 'static,
+        R: HandlerResult,
     {
-        add_muxer!(self, handler, Muxer::VoiceMux, [])
+        add_muxer!(self, "voice_fn", handler, Muxer::VoiceMux, [],
+                   [bot: &AwesomeBot, msg: &Message, a: Voice])
     }
 
     /// Add a routing handler that is triggered when an `Audio` or a `Voice` is received.
-    pub fn all_music_fn<H>(&mut self, handler: H) -> &mut AwesomeBot
+    pub fn all_music_fn<H, R>(&mut self, handler: H) -> &mut AwesomeBot
     where
-        H: Fn(&AwesomeBot, &Message, GeneralSound) + Send + Sync + 'static,
+        H: Fn(&AwesomeBot, &Message, GeneralSound) -> R + Send + Sync + 'static,
+        R: HandlerResult,
     {
-        add_muxer!(self, handler, Muxer::GeneralAudioMux, [])
+        add_muxer!(self, "all_music_fn", handler, Muxer::GeneralAudioMux, [],
+                   [bot: &AwesomeBot, msg: &Message, a: GeneralSound])
     }
 
     /// Add a contact routing handler.
-    pub fn contact_fn<H>(&mut self, handler: H) -> &mut AwesomeBot
+    pub fn contact_fn<H, R>(&mut self, handler: H) -> &mut AwesomeBot
     where
-        H: Fn(&AwesomeBot, &Message, Contact) + Send + Sync + 'static,
+        H: Fn(&AwesomeBot, &Message, Contact) -> R + Send + Sync + 'static,
+        R: HandlerResult,
     {
-        add_muxer!(self, handler, Muxer::ContactMux, [])
+        add_muxer!(self, "contact_fn", handler, Muxer::ContactMux, [],
+                   [bot: &AwesomeBot, msg: &Message, a: Contact])
     }
 
     /// Add a location routing handler.
-    pub fn location_fn<H>(&mut self, handler: H) -> &mut AwesomeBot
+    pub fn location_fn<H, R>(&mut self, handler: H) -> &mut AwesomeBot
     where
-        H: Fn(&AwesomeBot, &Message, Float, Float) + Send + Sync + 'static,
+        H: Fn(&AwesomeBot, &Message, Float, Float) -> R + Send + Sync + 'static,
+        R: HandlerResult,
     {
-        add_muxer!(self, handler, Muxer::LocationMux, [])
+        add_muxer!(self, "location_fn", handler, Muxer::LocationMux, [],
+                   [bot: &AwesomeBot, msg: &Message, a: Float, b: Float])
     }
 
     /// Add a routing handler that is triggered when a new participant enters a group.
-    pub fn new_participant_fn<H>(&mut self, handler: H) -> &mut AwesomeBot
+    pub fn new_participant_fn<H, R>(&mut self, handler: H) -> &mut AwesomeBot
     where
-        H: Fn(&AwesomeBot, &Message, User) + Send + Sync + 'static,
+        H: Fn(&AwesomeBot, &Message, User) -> R + Send + Sync + 'static,
+        R: HandlerResult,
     {
-        add_muxer!(self, handler, Muxer::NewParticipantMux, [])
+        add_muxer!(self, "new_participant_fn", handler, Muxer::NewParticipantMux, [],
+                   [bot: &AwesomeBot, msg: &Message, a: User])
     }
 
     /// Add a routing handler that is triggered when a participant leaves a group.
-    pub fn left_participant_fn<H>(&mut self, handler: H) -> &mut AwesomeBot
+    pub fn left_participant_fn<H, R>(&mut self, handler: H) -> &mut AwesomeBot
     where
-        H: Fn(&AwesomeBot, &Message, User) + Send + Sync + 'static,
+        H: Fn(&AwesomeBot, &Message, User) -> R + Send + Sync + 'static,
+        R: HandlerResult,
     {
-        add_muxer!(self, handler, Muxer::LeftParticipantMux, [])
+        add_muxer!(self, "left_participant_fn", handler, Muxer::LeftParticipantMux, [],
+                   [bot: &AwesomeBot, msg: &Message, a: User])
     }
 
     /// Add a routing handler that is triggered when the title of a group chat is changed.
-    pub fn new_title_fn<H>(&mut self, handler: H) -> &mut AwesomeBot
+    pub fn new_title_fn<H, R>(&mut self, handler: H) -> &mut AwesomeBot
     where
-        H: Fn(&AwesomeBot, &Message, String) + Send + Sync + 'static,
+        H: Fn(&AwesomeBot, &Message, String) -> R + Send + Sync + 'static,
+        R: HandlerResult,
     {
-        add_muxer!(self, handler, Muxer::NewTitleMux, [])
+        add_muxer!(self, "new_title_fn", handler, Muxer::NewTitleMux, [],
+                   [bot: &AwesomeBot, msg: &Message, a: String])
     }
 
     /// Add a routing handler that is triggered when the photo of a group chat is changed.
-    pub fn new_chat_photo_fn<H>(&mut self, handler: H) -> &mut AwesomeBot
+    pub fn new_chat_photo_fn<H, R>(&mut self, handler: H) -> &mut AwesomeBot
     where
-        H: Fn(&AwesomeBot, &Message, Vec<PhotoSize>) + Send + Sync + 'static,
+        H: Fn(&AwesomeBot, &Message, Vec<PhotoSize>) -> R + Send + Sync + 'static,
+        R: HandlerResult,
     {
-        add_muxer!(self, handler, Muxer::NewChatPhotoMux, [])
+        add_muxer!(self, "new_chat_photo_fn", handler, Muxer::NewChatPhotoMux, [],
+                   [bot: &AwesomeBot, msg: &Message, a: Vec<PhotoSize>])
     }
 
     /// Add a routing handler that is triggered when the photo of a group chat is deleted.
-    pub fn delete_chat_photo_fn<H>(&mut self, handler: H) -> &mut AwesomeBot
+    pub fn delete_chat_photo_fn<H, R>(&mut self, handler: H) -> &mut AwesomeBot
     where
-        H: Fn(&AwesomeBot, &Message, Chat) + Send + Sync + 'static,
+        H: Fn(&AwesomeBot, &Message, Chat) -> R + Send + Sync + 'static,
+        R: HandlerResult,
     {
-        add_muxer!(self, handler, Muxer::DeleteChatPhotoMux, [])
+        add_muxer!(self, "delete_chat_photo_fn", handler, Muxer::DeleteChatPhotoMux, [],
+                   [bot: &AwesomeBot, msg: &Message, a: Chat])
     }
 
     /// Add a routing handler that is triggered when a group chat is created.
-    pub fn group_chat_created_fn<H>(&mut self, handler: H) -> &mut AwesomeBot
+    pub fn group_chat_created_fn<H, R>(&mut self, handler: H) -> &mut AwesomeBot
     where
-        H: Fn(&AwesomeBot, &Message, Chat) + Send + Sync + 'static,
+        H: Fn(&AwesomeBot, &Message, Chat) -> R + Send + Sync + 'static,
+        R: HandlerResult,
     {
-        add_muxer!(self, handler, Muxer::GroupChatCreatedMux, [])
+        add_muxer!(self, "group_chat_created_fn", handler, Muxer::GroupChatCreatedMux, [],
+                   [bot: &AwesomeBot, msg: &Message, a: Chat])
     }
 
     /// Add a routing handler that is triggered when a super group chat is created.
-    pub fn super_group_chat_created_fn<H>(&mut self, handler: H) -> &mut AwesomeBot
+    pub fn super_group_chat_created_fn<H, R>(&mut self, handler: H) -> &mut AwesomeBot
     where
-        H: Fn(&AwesomeBot, &Message, GroupToSuperGroupMigration) + Send + Sync + 'static,
+        H: Fn(&AwesomeBot, &Message, GroupToSuperGroupMigration) -> R + Send + Sync + 'static,
+        R: HandlerResult,
     {
-        add_muxer!(self, handler, Muxer::SuperGroupChatCreatedMux, [])
+        add_muxer!(self, "super_group_chat_created_fn", handler, Muxer::SuperGroupChatCreatedMux, [],
+                   [bot: &AwesomeBot, msg: &Message, a: GroupToSuperGroupMigration])
     }
 
     /// Add a routing handler that is triggered when a channel chat is created.
-    pub fn channel_chat_created_fn<H>(&mut self, handler: H) -> &mut AwesomeBot
+    pub fn channel_chat_created_fn<H, R>(&mut self, handler: H) -> &mut AwesomeBot
     where
-        H: Fn(&AwesomeBot, &Message, Chat) + Send + Sync + 'static,
+        H: Fn(&AwesomeBot, &Message, Chat) -> R + Send + Sync + 'static,
+        R: HandlerResult,
     {
-        add_muxer!(self, handler, Muxer::ChannelChatCreatedMux, [])
+        add_muxer!(self, "channel_chat_created_fn", handler, Muxer::ChannelChatCreatedMux, [],
+                   [bot: &AwesomeBot, msg: &Message, a: Chat])
     }
 }
-
-// fn detect_file_or_id(name: &str, path: String) -> SendPath {
-//     // When PathExt becomes stable, use Path::new(&path).exists() instead of this!
-//     let check = fs::metadata(&path);
-//     if path.contains(".") && check.is_ok() && check.unwrap().is_file() {
-//         SendPath::File(name.to_owned(), Path::new(&path).to_path_buf())
-//     } else {
-//         SendPath::Id(name.to_owned(), path)
-//     }
-// }
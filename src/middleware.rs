@@ -0,0 +1,145 @@
+use update::Update;
+use AwesomeBot;
+
+/// What to do with an update after a middleware, returned by `Middleware::before`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flow {
+    /// Give the update to the next middleware, and to the routes after the last one.
+    Continue,
+    /// Don't handle the update, neither the next middlewares nor the routes receive it.
+    Stop,
+}
+
+/// Code that runs with every update, before and after the routes, added with
+/// `AwesomeBot::middleware`. Useful for logging, authorization, throttling, metrics...
+///
+/// The middlewares run in the order they were added. `before` can change the update
+/// (for example, to rewrite the text of the messages) or stop it. `after` runs in the
+/// reverse order, for all the middlewares whose `before` was called, even when
+/// the update was stopped.
+///
+/// ``` ignore
+/// struct Admins(Vec<Integer>);
+///
+/// impl Middleware for Admins {
+///     fn before(&self, _: &AwesomeBot, update: &mut Update) -> Flow {
+///         match update.message {
+///             Some(ref msg) if !self.0.contains(&msg.from.id) => Flow::Stop,
+///             _ => Flow::Continue,
+///         }
+///     }
+/// }
+///
+/// bot.middleware(Admins(vec![1234]));
+/// ```
+pub trait Middleware: Send + Sync {
+    /// Called when an update is received, before the routes.
+    fn before(&self, _bot: &AwesomeBot, _update: &mut Update) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called after the routes have handled the update, or after it was stopped.
+    fn after(&self, _bot: &AwesomeBot, _update: &Update) {}
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Flow, Middleware};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+    use telegram_bot::MessageType;
+    use test::fixtures::{test_bot, text_update};
+    use update::Update;
+    use AwesomeBot;
+
+    struct LogMiddleware {
+        name: &'static str,
+        flow: Flow,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Middleware for LogMiddleware {
+        fn before(&self, _: &AwesomeBot, update: &mut Update) -> Flow {
+            self.log.lock().unwrap().push(format!("before {}", self.name));
+            update.update_id += 1;
+            self.flow
+        }
+
+        fn after(&self, _: &AwesomeBot, update: &Update) {
+            let entry = format!("after {} {}", self.name, update.update_id);
+            self.log.lock().unwrap().push(entry);
+        }
+    }
+
+    #[test]
+    fn middleware_order_right() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut bot = test_bot();
+        let handler_log = log.clone();
+        bot.any_fn(move |_, _| handler_log.lock().unwrap().push(String::from("handler")));
+        for &(name, flow) in &[("a", Flow::Continue), ("b", Flow::Continue)] {
+            bot.middleware(LogMiddleware {
+                name: name,
+                flow: flow,
+                log: log.clone(),
+            });
+        }
+        bot.handle_update(text_update("Hi"));
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["before a", "before b", "handler", "after b 3", "after a 3"]
+        );
+
+        log.lock().unwrap().clear();
+        let mut bot = test_bot();
+        for &(name, flow) in &[("a", Flow::Stop), ("b", Flow::Continue)] {
+            bot.middleware(LogMiddleware {
+                name: name,
+                flow: flow,
+                log: log.clone(),
+            });
+        }
+        bot.handle_update(text_update("Hi"));
+        assert_eq!(*log.lock().unwrap(), vec!["before a", "after a 2"]);
+    }
+
+    // Stops the messages with the text "blocked"
+    struct Block;
+
+    impl Middleware for Block {
+        fn before(&self, _: &AwesomeBot, update: &mut Update) -> Flow {
+            match update.message.as_ref().map(|m| &m.msg) {
+                Some(&MessageType::Text(ref t)) if t == "blocked" => Flow::Stop,
+                _ => Flow::Continue,
+            }
+        }
+    }
+
+    #[test]
+    fn middleware_before_reply_right() {
+        let reply = Arc::new(Mutex::new(None));
+        let mut bot = test_bot();
+        bot.middleware(Block);
+        let got = reply.clone();
+        bot.simple_regex("^ask$", move |bot, msg, _| {
+            *got.lock().unwrap() = bot.wait_reply(msg, Duration::from_secs(5)).map(|m| m.msg);
+        });
+        let asking = {
+            let bot = bot.clone();
+            thread::spawn(move || bot.handle_update(text_update("ask")))
+        };
+        while !bot.replies.is_waiting(&text_update("blocked")) {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        // Stopped, so the handler keeps waiting for the next one
+        bot.handle_update(text_update("blocked"));
+        bot.handle_update(text_update("answer"));
+        asking.join().unwrap();
+        assert_eq!(
+            *reply.lock().unwrap(),
+            Some(MessageType::Text(String::from("answer")))
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use telegram_bot::Integer;
+
+/// Storage of the update offset, that is, the identifier of the next update to handle.
+///
+/// `simple_start` loads the offset from the store when starting and saves it each time
+/// the handlers of the updates finish, so after a restart the bot continues from the
+/// first update that wasn't completely handled, if Telegram still has it (updates may be
+/// handled twice). Check `AwesomeBot::start_with` to know which updates Telegram keeps.
+pub trait OffsetStore: Send + Sync {
+    /// Load the saved offset, `None` if there isn't any.
+    fn load(&self) -> io::Result<Option<Integer>>;
+    /// Save the offset.
+    fn save(&self, offset: Integer) -> io::Result<()>;
+}
+
+/// `OffsetStore` that keeps the offset in memory, only useful to restart the bot
+/// in the same process. This is the default store.
+#[derive(Default)]
+pub struct MemoryOffsetStore {
+    offset: Mutex<Option<Integer>>,
+}
+
+impl MemoryOffsetStore {
+    pub fn new() -> MemoryOffsetStore {
+        MemoryOffsetStore::default()
+    }
+}
+
+impl OffsetStore for MemoryOffsetStore {
+    fn load(&self) -> io::Result<Option<Integer>> {
+        Ok(*self.offset.lock().unwrap())
+    }
+
+    fn save(&self, offset: Integer) -> io::Result<()> {
+        *self.offset.lock().unwrap() = Some(offset);
+        Ok(())
+    }
+}
+
+/// `OffsetStore` that keeps the offset in a file, so it survives restarts and crashes.
+pub struct FileOffsetStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileOffsetStore {
+    /// Create a store that uses the file in `path`, it's created when saving the first time.
+    pub fn new<P: AsRef<Path>>(path: P) -> FileOffsetStore {
+        FileOffsetStore {
+            path: path.as_ref().to_path_buf(),
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+impl OffsetStore for FileOffsetStore {
+    fn load(&self) -> io::Result<Option<Integer>> {
+        let _guard = self.lock.lock().unwrap();
+        let mut content = String::new();
+        match fs::File::open(&self.path) {
+            Ok(mut f) => try!(f.read_to_string(&mut content)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        content
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn save(&self, offset: Integer) -> io::Result<()> {
+        let _guard = self.lock.lock().unwrap();
+        // Write and rename, so a crash never leaves a half written file
+        let tmp = self.path.with_extension("tmp");
+        {
+            let mut f = try!(fs::File::create(&tmp));
+            try!(write!(f, "{}", offset));
+            try!(f.sync_all());
+        }
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+struct TrackerState {
+    // Updates whose handlers are still running
+    pending: BTreeSet<Integer>,
+    // Offset after the last received update
+    received: Option<Integer>,
+    // Last offset saved in the store
+    committed: Option<Integer>,
+}
+
+// Keeps the updates being handled and saves in the store the offset of the first
+// update that it's not finished yet.
+pub(crate) struct OffsetTracker {
+    store: Arc<OffsetStore>,
+    state: Mutex<TrackerState>,
+}
+
+impl OffsetTracker {
+    pub(crate) fn new(store: Arc<OffsetStore>, start: Option<Integer>) -> OffsetTracker {
+        OffsetTracker {
+            store: store,
+            state: Mutex::new(TrackerState {
+                pending: BTreeSet::new(),
+                received: start,
+                committed: start,
+            }),
+        }
+    }
+
+    // Mark an update as received, its handlers are going to run. Returns false if it
+    // was already received, then it must not be handled again
+    pub(crate) fn start(&self, update_id: Integer) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.received.map_or(false, |r| update_id < r) {
+            return false;
+        }
+        state.pending.insert(update_id);
+        state.received = Some(update_id + 1);
+        true
+    }
+
+    // Mark an update as finished, saving the new offset if needed
+    pub(crate) fn finish(&self, update_id: Integer) {
+        let mut state = self.state.lock().unwrap();
+        state.pending.remove(&update_id);
+        let offset = match state.pending.iter().next() {
+            Some(&first) => Some(first),
+            None => state.received,
+        };
+        if let Some(o) = offset {
+            if state.committed.map_or(true, |c| c < o) {
+                // The errors saving are ignored, the offset will be saved again with the next update
+                if self.store.save(o).is_ok() {
+                    state.committed = Some(o);
+                }
+            }
+        }
+    }
+
+    // The offset after the last update received, to ask for the next ones
+    pub(crate) fn received(&self) -> Option<Integer> {
+        self.state.lock().unwrap().received
+    }
+
+    // The last offset saved
+    pub(crate) fn committed(&self) -> Option<Integer> {
+        self.state.lock().unwrap().committed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FileOffsetStore, MemoryOffsetStore, OffsetStore, OffsetTracker};
+    use std::fs;
+    use std::sync::Arc;
+    use test::fixtures::temp_path;
+
+    #[test]
+    fn offset_tracker_waits_pending_right() {
+        let store = Arc::new(MemoryOffsetStore::new());
+        let tracker = OffsetTracker::new(store.clone(), None);
+        assert!(tracker.start(1));
+        assert!(tracker.start(2));
+        assert!(tracker.start(3));
+        tracker.finish(2);
+        assert_eq!(store.load().unwrap(), Some(1));
+        tracker.finish(1);
+        assert_eq!(store.load().unwrap(), Some(3));
+        tracker.finish(3);
+        assert_eq!(store.load().unwrap(), Some(4));
+        assert_eq!(tracker.committed(), Some(4));
+    }
+
+    #[test]
+    fn offset_tracker_ignores_received_right() {
+        let store = Arc::new(MemoryOffsetStore::new());
+        let tracker = OffsetTracker::new(store, Some(5));
+        assert!(!tracker.start(4));
+        assert!(tracker.start(5));
+        assert!(tracker.start(6));
+        // Received again while they are running
+        assert!(!tracker.start(5));
+        assert!(!tracker.start(6));
+        tracker.finish(6);
+        assert_eq!(tracker.committed(), Some(5));
+        assert!(!tracker.start(6));
+        assert!(tracker.start(7));
+    }
+
+    #[test]
+    fn file_offset_store_right() {
+        let path = temp_path("offset");
+        let store = FileOffsetStore::new(&path);
+        store.save(42).unwrap();
+        assert_eq!(store.load().unwrap(), Some(42));
+        assert_eq!(FileOffsetStore::new(&path).load().unwrap(), Some(42));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(store.load().unwrap(), None);
+    }
+}
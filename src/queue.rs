@@ -0,0 +1,353 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use telegram_bot::Integer;
+use threadpool::ThreadPool;
+
+// Sends of different chats running at the same time
+const SEND_THREADS: usize = 8;
+
+/// Limits of the messages sent by the bot, the default ones are the limits
+/// recommended by Telegram.
+///
+/// When a limit is reached, the send waits until it can be done.
+/// The limits set to 0 are not applied.
+#[derive(Clone, Debug)]
+pub struct RateLimits {
+    /// Maximum messages per second to all the chats, 30 by default.
+    pub global_per_second: usize,
+    /// Minimum time between two messages to the same chat, 1 second by default.
+    pub chat_interval: Duration,
+    /// Maximum messages per minute to the same group, 20 by default.
+    pub group_per_minute: usize,
+}
+
+impl Default for RateLimits {
+    fn default() -> RateLimits {
+        RateLimits {
+            global_per_second: 30,
+            chat_interval: Duration::from_secs(1),
+            group_per_minute: 20,
+        }
+    }
+}
+
+struct LimiterState {
+    // Sends of the last second
+    global: VecDeque<Instant>,
+    // Sends of the last minute by chat
+    chats: HashMap<Integer, VecDeque<Instant>>,
+    // Telegram asked to wait (`retry_after`) until then, for everything (`None`) or a chat
+    blocked: HashMap<Option<Integer>, Instant>,
+}
+
+// The sends done, to know how much the next ones have to wait
+struct Limiter {
+    limits: RateLimits,
+    state: Mutex<LimiterState>,
+}
+
+type Job = Box<FnOnce() + Send>;
+
+struct Jobs {
+    // Sends enqueued by chat (`None` for the ones without chat), in order,
+    // with whether they count for the limits
+    queued: HashMap<Option<Integer>, VecDeque<(bool, Job)>>,
+    // Chats with a send running, their next send waits for it to keep the order
+    running: HashSet<Option<Integer>>,
+    // The thread that starts the sends is running
+    started: bool,
+    // The queue was dropped, the thread stops once everything is sent
+    closed: bool,
+}
+
+/// Send queue of a bot, shared by all the clones of the `Client`. It makes the sends
+/// wait to respect the `RateLimits`, and runs the sends enqueued in the background.
+///
+/// The enqueued sends of each chat run in order, but a chat waiting for its limits
+/// doesn't delay the rest of chats.
+pub struct SendQueue {
+    limiter: Arc<Limiter>,
+    jobs: Arc<(Mutex<Jobs>, Condvar)>,
+}
+
+impl SendQueue {
+    pub fn new(limits: RateLimits) -> SendQueue {
+        SendQueue {
+            limiter: Arc::new(Limiter {
+                limits: limits,
+                state: Mutex::new(LimiterState {
+                    global: VecDeque::new(),
+                    chats: HashMap::new(),
+                    blocked: HashMap::new(),
+                }),
+            }),
+            jobs: Arc::new((
+                Mutex::new(Jobs {
+                    queued: HashMap::new(),
+                    running: HashSet::new(),
+                    started: false,
+                    closed: false,
+                }),
+                Condvar::new(),
+            )),
+        }
+    }
+
+    /// Block until a message can be sent to `chat` (or to any chat if it's `None`),
+    /// and count it as sent.
+    pub fn acquire(&self, chat: Option<Integer>) {
+        loop {
+            let wait = {
+                let mut state = self.limiter.state.lock().unwrap();
+                let now = Instant::now();
+                let wait = self.limiter.wait_time(&mut state, chat, now);
+                if wait == Duration::from_secs(0) {
+                    state.global.push_back(now);
+                    if let Some(id) = chat {
+                        state.chats.entry(id).or_insert_with(VecDeque::new).push_back(now);
+                    }
+                    return;
+                }
+                wait
+            };
+            thread::sleep(wait);
+        }
+    }
+
+    /// Telegram answered that we have to wait `seconds` before sending again to `chat`.
+    pub fn block(&self, chat: Option<Integer>, seconds: u64) {
+        let until = Instant::now() + Duration::from_secs(seconds);
+        let mut state = self.limiter.state.lock().unwrap();
+        let entry = state.blocked.entry(chat).or_insert(until);
+        if *entry < until {
+            *entry = until;
+        }
+    }
+
+    /// Run `job` in the background, after the jobs enqueued before for the same `chat`.
+    /// If `limited`, it starts once a message can be sent to `chat` (`job` still has
+    /// to `acquire` it).
+    pub fn enqueue<F: FnOnce() + Send + 'static>(&self, chat: Option<Integer>, limited: bool, job: F) {
+        let &(ref lock, ref changed) = &*self.jobs;
+        let job: Job = Box::new(job);
+        let mut jobs = lock.lock().unwrap();
+        jobs.queued
+            .entry(chat)
+            .or_insert_with(VecDeque::new)
+            .push_back((limited, job));
+        if !jobs.started {
+            jobs.started = true;
+            let limiter = self.limiter.clone();
+            let shared = self.jobs.clone();
+            thread::spawn(move || schedule(&limiter, &shared));
+        }
+        changed.notify_all();
+    }
+}
+
+impl Drop for SendQueue {
+    fn drop(&mut self) {
+        let &(ref lock, ref changed) = &*self.jobs;
+        lock.lock().unwrap().closed = true;
+        changed.notify_all();
+    }
+}
+
+impl Limiter {
+    // Time to wait before sending to `chat`, cleaning the old sends
+    fn wait_time(&self, state: &mut LimiterState, chat: Option<Integer>, now: Instant) -> Duration {
+        let second = Duration::from_secs(1);
+        let minute = Duration::from_secs(60);
+        let mut until = now;
+
+        state.blocked.retain(|_, t| *t > now);
+        for key in &[None, chat] {
+            if let Some(&t) = state.blocked.get(key) {
+                until = until.max(t);
+            }
+        }
+
+        while state.global.front().map_or(false, |t| now - *t >= second) {
+            state.global.pop_front();
+        }
+        let global = self.limits.global_per_second;
+        if global > 0 && state.global.len() >= global {
+            until = until.max(state.global[state.global.len() - global] + second);
+        }
+
+        if let Some(id) = chat {
+            let sends = state.chats.entry(id).or_insert_with(VecDeque::new);
+            while sends.front().map_or(false, |t| now - *t >= minute) {
+                sends.pop_front();
+            }
+            if let Some(&last) = sends.back() {
+                until = until.max(last + self.limits.chat_interval);
+            }
+            // The groups have negative ids
+            let group = self.limits.group_per_minute;
+            if id < 0 && group > 0 && sends.len() >= group {
+                until = until.max(sends[sends.len() - group] + minute);
+            }
+        }
+        state.chats.retain(|_, sends| !sends.is_empty());
+
+        until - now
+    }
+}
+
+// Start the enqueued sends, each one when its chat can send and the previous send
+// of the chat has finished. It runs until the queue is dropped.
+fn schedule(limiter: &Limiter, shared: &Arc<(Mutex<Jobs>, Condvar)>) {
+    let &(ref lock, ref changed) = &**shared;
+    // When all the threads are busy, the sends started wait for a free one
+    let pool = ThreadPool::new(SEND_THREADS);
+    let mut jobs = lock.lock().unwrap();
+    loop {
+        // The chat that can send first, between the ones without a send running
+        let now = Instant::now();
+        let next = {
+            let mut state = limiter.state.lock().unwrap();
+            jobs.queued
+                .iter()
+                .filter(|&(chat, _)| !jobs.running.contains(chat))
+                .filter_map(|(&chat, queued)| queued.front().map(|&(limited, _)| (limited, chat)))
+                .map(|(limited, chat)| {
+                    let wait = if limited {
+                        limiter.wait_time(&mut state, chat, now)
+                    } else {
+                        Duration::from_secs(0)
+                    };
+                    (wait, chat)
+                })
+                .min()
+        };
+
+        match next {
+            Some((wait, chat)) if wait == Duration::from_secs(0) => {
+                let job = match jobs.queued.get_mut(&chat).and_then(|q| q.pop_front()) {
+                    Some((_, job)) => job,
+                    None => continue,
+                };
+                if jobs.queued.get(&chat).map_or(false, |q| q.is_empty()) {
+                    jobs.queued.remove(&chat);
+                }
+                jobs.running.insert(chat);
+                let shared = shared.clone();
+                pool.execute(move || {
+                    job();
+                    let &(ref lock, ref changed) = &*shared;
+                    lock.lock().unwrap().running.remove(&chat);
+                    changed.notify_all();
+                });
+            }
+            Some((wait, _)) => jobs = changed.wait_timeout(jobs, wait).unwrap().0,
+            None if jobs.closed && jobs.running.is_empty() => return,
+            None => jobs = changed.wait(jobs).unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RateLimits, SendQueue, SEND_THREADS};
+    use std::cmp;
+    use std::sync::mpsc;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn send_queue_chat_interval_right() {
+        let queue = SendQueue::new(RateLimits {
+            global_per_second: 30,
+            chat_interval: Duration::from_millis(100),
+            group_per_minute: 20,
+        });
+        let start = Instant::now();
+        queue.acquire(Some(1));
+        queue.acquire(Some(2));
+        assert!(start.elapsed() < Duration::from_millis(100));
+        queue.acquire(Some(1));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn send_queue_zero_global_limit_right() {
+        let queue = SendQueue::new(RateLimits {
+            global_per_second: 0,
+            chat_interval: Duration::from_secs(0),
+            group_per_minute: 20,
+        });
+        let start = Instant::now();
+        for chat in 0..50 {
+            queue.acquire(Some(chat));
+        }
+        queue.acquire(None);
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+
+    #[test]
+    fn send_queue_zero_group_limit_right() {
+        let queue = SendQueue::new(RateLimits {
+            global_per_second: 100,
+            chat_interval: Duration::from_secs(0),
+            group_per_minute: 0,
+        });
+        let start = Instant::now();
+        for _ in 0..30 {
+            queue.acquire(Some(-100));
+        }
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+
+    #[test]
+    fn send_queue_chats_dont_wait_others_right() {
+        let queue = Arc::new(SendQueue::new(RateLimits {
+            global_per_second: 30,
+            chat_interval: Duration::from_millis(300),
+            group_per_minute: 20,
+        }));
+        let (tx, rx) = mpsc::channel();
+        let start = Instant::now();
+        for &(chat, n) in &[(1, 1), (1, 2), (2, 1)] {
+            let (sender, tx) = (queue.clone(), tx.clone());
+            queue.enqueue(Some(chat), true, move || {
+                sender.acquire(Some(chat));
+                tx.send((chat, n, start.elapsed())).unwrap();
+            });
+        }
+        let mut sent: Vec<_> = rx.iter().take(3).collect();
+        sent.sort_by_key(|&(chat, n, _)| (chat, n));
+
+        // The second message to the chat 1 waits, in order, but the chat 2 doesn't wait for it
+        assert!(sent[0].2 < Duration::from_millis(300));
+        assert!(sent[1].2 >= Duration::from_millis(300));
+        assert!(sent[2].2 < Duration::from_millis(300));
+    }
+
+    #[test]
+    fn send_queue_bounded_threads_right() {
+        let queue = SendQueue::new(RateLimits::default());
+        // (running now, most running at the same time)
+        let running = Arc::new(Mutex::new((0, 0)));
+        let (tx, rx) = mpsc::channel();
+        for chat in 0..(SEND_THREADS as i64 * 3) {
+            let (running, tx) = (running.clone(), tx.clone());
+            queue.enqueue(Some(chat), false, move || {
+                {
+                    let mut r = running.lock().unwrap();
+                    r.0 += 1;
+                    r.1 = cmp::max(r.0, r.1);
+                }
+                thread::sleep(Duration::from_millis(20));
+                running.lock().unwrap().0 -= 1;
+                tx.send(chat).unwrap();
+            });
+        }
+        assert_eq!(rx.iter().take(SEND_THREADS * 3).count(), SEND_THREADS * 3);
+        assert!(running.lock().unwrap().1 <= SEND_THREADS);
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use telegram_bot::{Integer, Message};
+use update::Update;
+
+struct Waiting {
+    // Identifies each wait, so a finished one doesn't remove a newer one
+    next_id: u64,
+    senders: HashMap<(Integer, Integer), (u64, Sender<Message>)>,
+}
+
+// The handlers waiting for a reply of a user in a chat, by (chat, user)
+pub(crate) struct Replies {
+    waiting: Mutex<Waiting>,
+}
+
+impl Replies {
+    pub fn new() -> Replies {
+        Replies {
+            waiting: Mutex::new(Waiting {
+                next_id: 0,
+                senders: HashMap::new(),
+            }),
+        }
+    }
+
+    // Wait for the next message of the user in the chat, if another handler was
+    // waiting for the same one, it stops waiting
+    pub fn wait(&self, key: (Integer, Integer), timeout: Duration) -> Option<Message> {
+        let (tx, rx) = mpsc::channel();
+        let id = {
+            let mut waiting = self.waiting.lock().unwrap();
+            let id = waiting.next_id;
+            waiting.next_id += 1;
+            waiting.senders.insert(key, (id, tx));
+            id
+        };
+        if let Ok(reply) = rx.recv_timeout(timeout) {
+            return Some(reply);
+        }
+        let mut waiting = self.waiting.lock().unwrap();
+        if waiting.senders.get(&key).map_or(false, |&(i, _)| i == id) {
+            waiting.senders.remove(&key);
+        }
+        // The message may have been given just after the timeout, `deliver` sends it
+        // with the lock taken, so now it's in the channel or it will never be sent
+        rx.try_recv().ok()
+    }
+
+    // Returns true if a handler is waiting for the message of the update
+    pub fn is_waiting(&self, update: &Update) -> bool {
+        match update.message {
+            Some(ref m) => self.waiting.lock().unwrap().senders.contains_key(&key(m)),
+            None => false,
+        }
+    }
+
+    // Give the message of the update to the handler waiting for it, if there is one.
+    // Returns true if it was given, then it must not be handled.
+    pub fn deliver(&self, update: &Update) -> bool {
+        let message = match update.message {
+            Some(ref m) => m,
+            None => return false,
+        };
+        let mut waiting = self.waiting.lock().unwrap();
+        match waiting.senders.remove(&key(message)) {
+            // The waits remove themselves with the lock taken, so it only fails if the
+            // handler is gone
+            Some((_, tx)) => tx.send(message.clone()).is_ok(),
+            None => false,
+        }
+    }
+}
+
+// The wait of the message
+fn key(message: &Message) -> (Integer, Integer) {
+    (message.chat.id(), message.from.id)
+}
+
+#[cfg(test)]
+mod test {
+    use super::Replies;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+    use test::fixtures::text_update;
+
+    #[test]
+    fn wait_reply_right() {
+        let update = text_update("Bob");
+        let replies = Arc::new(Replies::new());
+        assert!(!replies.is_waiting(&update));
+        assert!(!replies.deliver(&update));
+
+        let waiting = replies.clone();
+        let handle = thread::spawn(move || waiting.wait((1, 2), Duration::from_secs(5)));
+        while !replies.deliver(&update) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(handle.join().unwrap().map(|m| m.message_id), Some(5));
+
+        assert!(replies.wait((1, 2), Duration::from_millis(10)).is_none());
+        assert!(!replies.deliver(&update));
+    }
+
+    #[test]
+    fn wait_reply_timeout_race_right() {
+        let update = text_update("Bob");
+        let replies = Arc::new(Replies::new());
+        // Deliver around the timeout, the message is never lost
+        for i in 0..200 {
+            let waiting = replies.clone();
+            let handle = thread::spawn(move || waiting.wait((1, 2), Duration::from_millis(1)));
+            thread::sleep(Duration::new(0, i * 10000));
+            let delivered = replies.deliver(&update);
+            assert_eq!(handle.join().unwrap().is_some(), delivered);
+        }
+    }
+}
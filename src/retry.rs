@@ -0,0 +1,303 @@
+use std::cmp;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hyper;
+use shutdown::ShutdownHandle;
+use telegram_bot::{Error, Result};
+
+// Delays computed, hashed to get a different jitter each time
+static JITTER_CALLS: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Policy to repeat the calls to the Bot API that fail with a transient error,
+/// used when receiving the updates (`simple_start`) and when sending.
+///
+/// Between the attempts it waits an exponential backoff (the delay is multiplied
+/// by `multiplier` after each attempt, up to `max_delay`), with some random jitter.
+/// By default it makes up to 5 attempts, retrying only the network errors.
+/// When the bot is stopped, it doesn't retry anymore.
+///
+/// Keep in mind that a send that failed in the network could have reached Telegram,
+/// so a retried message may be sent twice.
+///
+/// ``` ignore
+/// let bot = AwesomeBotBuilder::new(&token)
+///     .retry_policy(RetryPolicy::new().max_attempts(10).on_retry(|method, attempt, e, _| {
+///         println!("{} failed ({}), attempt {}", method, e, attempt);
+///     }))
+///     .build();
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: usize,
+    initial_delay: Duration,
+    max_delay: Duration,
+    multiplier: u32,
+    jitter: f64,
+    retryable: Arc<Fn(&Error) -> bool + Send + Sync>,
+    on_retry: Option<Arc<Fn(&str, usize, &Error, Duration) + Send + Sync>>,
+    // The retries stop when this bot is stopped
+    shutdown: Option<ShutdownHandle>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2,
+            jitter: 0.2,
+            retryable: Arc::new(is_transient),
+            on_retry: None,
+            shutdown: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The default policy.
+    pub fn new() -> RetryPolicy {
+        RetryPolicy::default()
+    }
+
+    /// A policy that never retries.
+    pub fn none() -> RetryPolicy {
+        let mut policy = RetryPolicy::default();
+        policy.max_attempts(1);
+        policy
+    }
+
+    /// Set the maximum number of attempts (the first call included), 0 or 1 to never retry.
+    pub fn max_attempts(&mut self, n: usize) -> &mut RetryPolicy {
+        self.max_attempts = n;
+        self
+    }
+
+    /// Set the delay after the first failure, 1 second by default.
+    pub fn initial_delay(&mut self, delay: Duration) -> &mut RetryPolicy {
+        self.initial_delay = delay;
+        self
+    }
+
+    /// Set the maximum delay between two attempts, 30 seconds by default.
+    pub fn max_delay(&mut self, delay: Duration) -> &mut RetryPolicy {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Set how much the delay grows after each attempt, 2 by default.
+    pub fn multiplier(&mut self, m: u32) -> &mut RetryPolicy {
+        self.multiplier = m;
+        self
+    }
+
+    /// Set the random variation of the delays, as a fraction of the delay (0.2 is ±20%),
+    /// 0.2 by default.
+    pub fn jitter(&mut self, fraction: f64) -> &mut RetryPolicy {
+        self.jitter = fraction.max(0.0).min(1.0);
+        self
+    }
+
+    /// Set which errors are retried, by default the network errors.
+    pub fn retryable<F>(&mut self, f: F) -> &mut RetryPolicy
+    where
+        F: Fn(&Error) -> bool + Send + Sync + 'static,
+    {
+        self.retryable = Arc::new(f);
+        self
+    }
+
+    /// Set a function called before each retry, with the Bot API method, the number of the
+    /// attempt that failed, its error and the time to wait until the next one.
+    pub fn on_retry<F>(&mut self, f: F) -> &mut RetryPolicy
+    where
+        F: Fn(&str, usize, &Error, Duration) + Send + Sync + 'static,
+    {
+        self.on_retry = Some(Arc::new(f));
+        self
+    }
+
+    // Stop retrying when the bot of `shutdown` is stopped
+    pub(crate) fn stop_on(&mut self, shutdown: ShutdownHandle) {
+        self.shutdown = Some(shutdown);
+    }
+
+    /// Call `f` until it succeeds, fails with an error not retryable, there are no
+    /// more attempts, or the bot is stopped.
+    pub(crate) fn run<T, F: FnMut() -> Result<T>>(&self, method: &str, mut f: F) -> Result<T> {
+        let mut attempt = 1;
+        loop {
+            let e = match f() {
+                Ok(v) => return Ok(v),
+                Err(e) => e,
+            };
+            if attempt >= self.max_attempts || !(self.retryable)(&e) {
+                return Err(e);
+            }
+            let delay = self.delay(attempt);
+            if let Some(ref hook) = self.on_retry {
+                hook(method, attempt, &e, delay);
+            }
+            if !self.wait(delay) {
+                return Err(e);
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Wait `delay`, returning false without waiting it all if the bot is stopped.
+    pub(crate) fn wait(&self, delay: Duration) -> bool {
+        // Wait in short steps, to notice soon if the bot is stopped
+        let until = Instant::now() + delay;
+        loop {
+            if self.shutdown.as_ref().map_or(false, |s| s.is_shutdown()) {
+                return false;
+            }
+            let now = Instant::now();
+            if now >= until {
+                return true;
+            }
+            thread::sleep(cmp::min(until - now, Duration::from_millis(100)));
+        }
+    }
+
+    /// Time to wait after the failed `attempt` (starting at 1).
+    pub(crate) fn delay(&self, attempt: usize) -> Duration {
+        let mut delay = self.initial_delay;
+        for _ in 1..attempt {
+            delay = delay * self.multiplier;
+            if delay >= self.max_delay {
+                break;
+            }
+        }
+        let delay = delay.min(self.max_delay);
+
+        let millis = delay.as_secs() as f64 * 1000.0 + (delay.subsec_nanos() / 1_000_000) as f64;
+        Duration::from_millis((millis * self.jitter_factor()) as u64)
+    }
+
+    // Random number between `1 - jitter` and `1 + jitter`. The keys of `RandomState`
+    // are random, so hashing a counter with them is random enough without more crates.
+    fn jitter_factor(&self) -> f64 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_usize(JITTER_CALLS.fetch_add(1, Ordering::Relaxed));
+        let r = (hasher.finish() % 1_000_000) as f64 / 1_000_000.0;
+        1.0 + self.jitter * (2.0 * r - 1.0)
+    }
+}
+
+/// The default retryable errors: the network failures, not the ones answered by Telegram,
+/// the malformed requests or responses, or the local files that can't be read.
+pub fn is_transient(e: &Error) -> bool {
+    match *e {
+        Error::Http(hyper::Error::Io(ref e)) | Error::Io(ref e) => is_connection_error(e),
+        _ => false,
+    }
+}
+
+// The connection failed or was lost, trying again may work
+fn is_connection_error(e: &io::Error) -> bool {
+    match e.kind() {
+        io::ErrorKind::ConnectionRefused
+        | io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::NotConnected
+        | io::ErrorKind::BrokenPipe
+        | io::ErrorKind::TimedOut
+        | io::ErrorKind::Interrupted
+        | io::ErrorKind::UnexpectedEof => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_transient, RetryPolicy};
+    use hyper;
+    use shutdown::ShutdownHandle;
+    use std::cell::Cell;
+    use std::io;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use telegram_bot::Error;
+
+    // A call that always fails with a network error
+    fn failing(calls: &Cell<usize>) -> Result<(), Error> {
+        calls.set(calls.get() + 1);
+        Err(Error::Io(io::Error::new(io::ErrorKind::ConnectionRefused, "refused")))
+    }
+
+    #[test]
+    fn retry_delay_right() {
+        let mut policy = RetryPolicy::new();
+        policy
+            .initial_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(500))
+            .jitter(0.0);
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(400));
+        assert_eq!(policy.delay(4), Duration::from_millis(500));
+        assert_eq!(policy.delay(50), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn retry_jitter_right() {
+        let mut policy = RetryPolicy::new();
+        policy.initial_delay(Duration::from_secs(1)).jitter(0.5);
+        let delays: Vec<Duration> = (0..20).map(|_| policy.delay(1)).collect();
+        for d in &delays {
+            assert!(*d >= Duration::from_millis(500) && *d <= Duration::from_millis(1500));
+        }
+        assert!(delays.iter().any(|d| *d != delays[0]));
+    }
+
+    #[test]
+    fn retry_attempts_right() {
+        let calls = Cell::new(0);
+        let mut policy = RetryPolicy::new();
+        policy.initial_delay(Duration::from_millis(1)).jitter(0.0);
+        for &(attempts, expected) in &[(0, 1), (1, 1), (3, 3)] {
+            calls.set(0);
+            policy.max_attempts(attempts);
+            assert!(policy.run("getMe", || failing(&calls)).is_err());
+            assert_eq!(calls.get(), expected);
+        }
+    }
+
+    #[test]
+    fn retry_stops_on_shutdown_right() {
+        let shutdown = ShutdownHandle::new();
+        let mut policy = RetryPolicy::new();
+        policy.initial_delay(Duration::from_secs(10)).max_attempts(100);
+        policy.stop_on(shutdown.clone());
+        let handle = shutdown.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            handle.shutdown();
+        });
+
+        let calls = Cell::new(0);
+        let start = Instant::now();
+        assert!(policy.run("getMe", || failing(&calls)).is_err());
+        assert_eq!(calls.get(), 1);
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn is_transient_right() {
+        let refused = || io::Error::new(io::ErrorKind::ConnectionRefused, "refused");
+        assert!(is_transient(&Error::Io(refused())));
+        assert!(is_transient(&Error::Http(hyper::Error::Io(refused()))));
+        let missing = io::Error::new(io::ErrorKind::NotFound, "missing");
+        assert!(!is_transient(&Error::Io(missing)));
+        assert!(!is_transient(&Error::Http(hyper::Error::TooLarge)));
+        assert!(!is_transient(&Error::Http(hyper::Error::Status)));
+    }
+}
@@ -1,9 +1,45 @@
+use api::{Client, Params};
+use file::InputFile;
+use inline::InlineQueryResult;
+use keyboard::Markup;
 use rustc_serialize::Decodable;
 use telegram_bot::*;
+use AwesomeBot;
 
 /// Help trait indicating that at least the `end` method is implemented for the SendBuilder structs
+///
+/// The messages wait in the send queue of the bot to respect Telegram's rate limits
+/// (check `RateLimits`), so `end` can block for a while if the bot sends a lot.
 pub trait Finisher<T: Decodable> {
+    /// Send it and wait for Telegram's answer.
     fn end(&mut self) -> Result<T>;
+
+    /// Send it in the background, without waiting. The errors are given to the
+    /// `on_send_error` hook of the bot.
+    ///
+    /// The builders of this crate implement it, by default it's sent with `end`
+    /// (waiting, and ignoring the error).
+    fn enqueue(&mut self) {
+        let _ = self.end();
+    }
+}
+
+// Implement `Finisher` for a struct with a `request` method, that returns
+// the client, the method and the parameters to call
+macro_rules! finisher {
+    ($name:ident, $t:ty) => {
+        impl Finisher<$t> for $name {
+            fn end(&mut self) -> Result<$t> {
+                let (bot, method, params) = self.request();
+                bot.call(method, params)
+            }
+
+            fn enqueue(&mut self) {
+                let (bot, method, params) = self.request();
+                bot.enqueue(method, params);
+            }
+        }
+    };
 }
 
 /// SendBuilder it's a builder struct that allows you to construct answers in
@@ -12,13 +48,19 @@ pub trait Finisher<T: Decodable> {
 #[derive(Clone)]
 pub struct SendBuilder {
     chat_id: Integer,
-    bot: Api,
+    bot: Client,
 }
 
 impl SendBuilder {
     /// Create a new SendBuilder, don't use it,
     /// use the `send` and `answer` methods of `AwesomeBot` :)
-    pub fn new(id: Integer, bot: Api) -> SendBuilder {
+    #[deprecated(note = "use the `send` and `answer` methods of `AwesomeBot`")]
+    pub fn new(id: Integer, bot: &AwesomeBot) -> SendBuilder {
+        bot.send(id)
+    }
+
+    // The SendBuilder of `send` and `answer`
+    pub(crate) fn with_client(id: Integer, bot: Client) -> SendBuilder {
         SendBuilder {
             chat_id: id,
             bot: bot,
@@ -37,22 +79,22 @@ impl SendBuilder {
         }
     }
 
-    /// Start a photo constructor to send.
-    pub fn photo(self, t: &str) -> SendPhoto {
+    /// Start a photo constructor to send, the file is an `InputFile` or a `Path`.
+    pub fn photo<F: Into<InputFile>>(self, f: F) -> SendPhoto {
         SendPhoto {
             send: self,
-            photo: t.to_string(),
+            photo: f.into(),
             caption: None,
             reply_to_message_id: None,
             reply_markup: None,
         }
     }
 
-    /// Start an audio constructor to send.
-    pub fn audio(self, t: &str) -> SendAudio {
+    /// Start an audio constructor to send, the file is an `InputFile` or a `Path`.
+    pub fn audio<F: Into<InputFile>>(self, f: F) -> SendAudio {
         SendAudio {
             send: self,
-            audio: t.to_string(),
+            audio: f.into(),
             duration: None,
             performer: None,
             title: None,
@@ -61,42 +103,42 @@ impl SendBuilder {
         }
     }
 
-    /// Start a voice constructor to send.
-    pub fn voice(self, t: &str) -> SendVoice {
+    /// Start a voice constructor to send, the file is an `InputFile` or a `Path`.
+    pub fn voice<F: Into<InputFile>>(self, f: F) -> SendVoice {
         SendVoice {
             send: self,
-            voice: t.to_string(),
+            voice: f.into(),
             duration: None,
             reply_to_message_id: None,
             reply_markup: None,
         }
     }
 
-    /// Start a document constructor to send.
-    pub fn document(self, t: &str) -> SendDocument {
+    /// Start a document constructor to send, the file is an `InputFile` or a `Path`.
+    pub fn document<F: Into<InputFile>>(self, f: F) -> SendDocument {
         SendDocument {
             send: self,
-            document: t.to_string(),
+            document: f.into(),
             reply_to_message_id: None,
             reply_markup: None,
         }
     }
 
-    /// Start a sticker constructor to send.
-    pub fn sticker(self, t: &str) -> SendSticker {
+    /// Start a sticker constructor to send, the file is an `InputFile` or a `Path`.
+    pub fn sticker<F: Into<InputFile>>(self, f: F) -> SendSticker {
         SendSticker {
             send: self,
-            sticker: t.to_string(),
+            sticker: f.into(),
             reply_to_message_id: None,
             reply_markup: None,
         }
     }
 
-    /// Start a video constructor to send.
-    pub fn video(self, t: &str) -> SendVideo {
+    /// Start a video constructor to send, the file is an `InputFile` or a `Path`.
+    pub fn video<F: Into<InputFile>>(self, f: F) -> SendVideo {
         SendVideo {
             send: self,
-            video: t.to_string(),
+            video: f.into(),
             caption: None,
             duration: None,
             reply_to_message_id: None,
@@ -167,21 +209,22 @@ macro_rules! addkeyboardfuncs {
         /// Add keyboard methods to the struct, only one of these will be sent,
         /// and it will be the last one used.
         impl $name {
-            /// Add a keyboard to the reply
-            pub fn keyboard(&mut self, r: ReplyKeyboardMarkup) -> &mut $name {
-                self.$markname = Some(ReplyMarkup::from(r));
+            /// Add a keyboard to the reply, a `Keyboard` made with `KeyboardBuilder`
+            /// (reply or inline) or a `ReplyKeyboardMarkup`
+            pub fn keyboard<K: Into<Markup>>(&mut self, k: K) -> &mut $name {
+                self.$markname = Some(k.into());
                 self
             }
 
             /// Hide the keyboard
             pub fn hide(&mut self, h: bool) -> &mut $name {
-                self.$markname = Some(ReplyMarkup::KeyboardHide(h));
+                self.$markname = Some(Markup::from(ReplyMarkup::KeyboardHide(h)));
                 self
             }
 
             /// Force the reply to this message
             pub fn force(&mut self, f: bool) -> &mut $name {
-                self.$markname = Some(ReplyMarkup::ForceReply(f));
+                self.$markname = Some(Markup::from(ReplyMarkup::ForceReply(f)));
                 self
             }
         }
@@ -194,194 +237,383 @@ basesendtype!(SendText,
               [parse_mode => (parse_mode, ParseMode, "Set `ParseMode` for the message"),
                disable_webpage_preview => (disable_preview, bool, "Set `true` to disable the link preview in the message."),
                reply_to_message_id => (reply_id, Integer, "Set a message ID to reply to with this message."),
-               reply_markup => (markup, ReplyMarkup, "Set a `ReplyMarkup` to send, but instead of directly using this, use the `keyboard`, `hide` or `force` methods")]);
+               reply_markup => (markup, Markup, "Set a `Markup` to send, but instead of directly using this, use the `keyboard`, `hide` or `force` methods")]);
 
 addkeyboardfuncs!(SendText, reply_markup);
 
-impl Finisher<Message> for SendText {
-    fn end(&mut self) -> Result<Message> {
-        self.send.bot.send_message(
-            self.send.chat_id,
-            self.text.clone(),
-            self.parse_mode,
-            self.disable_webpage_preview,
-            self.reply_to_message_id,
-            self.reply_markup.clone(),
-        )
+impl SendText {
+    fn request(&self) -> (&Client, &'static str, Params) {
+        let params = Params::new()
+            .add("chat_id", self.send.chat_id)
+            .add("text", self.text.clone())
+            .opt("parse_mode", self.parse_mode.map(|p| p.to_string()))
+            .opt("disable_web_page_preview", self.disable_webpage_preview)
+            .opt("reply_to_message_id", self.reply_to_message_id)
+            .opt("reply_markup", self.reply_markup.clone());
+        (&self.send.bot, "sendMessage", params)
     }
 }
 
+finisher!(SendText, Message);
+
 basesendtype!(SendPhoto,
               "`Photo`",
-              [photo => String],
+              [photo => InputFile],
               [caption => (caption, String, "Set a caption to be included with the message."),
                reply_to_message_id => (reply_id, Integer, "Set a message ID to reply with this message."),
-               reply_markup => (markup, ReplyMarkup, "Set a `ReplyMarkup` to send, but instead of this, use the `keyboard`, `hide` or `force` methods")]);
+               reply_markup => (markup, Markup, "Set a `Markup` to send, but instead of this, use the `keyboard`, `hide` or `force` methods")]);
 
 addkeyboardfuncs!(SendPhoto, reply_markup);
 
-impl Finisher<Message> for SendPhoto {
-    fn end(&mut self) -> Result<Message> {
-        self.send.bot.send_photo(
-            self.send.chat_id,
-            self.photo.clone(),
-            self.caption.clone(),
-            self.reply_to_message_id,
-            self.reply_markup.clone(),
-        )
+impl SendPhoto {
+    fn request(&self) -> (&Client, &'static str, Params) {
+        let params = Params::new()
+            .add("chat_id", self.send.chat_id)
+            .file("photo", self.photo.clone())
+            .opt("caption", self.caption.clone())
+            .opt("reply_to_message_id", self.reply_to_message_id)
+            .opt("reply_markup", self.reply_markup.clone());
+        (&self.send.bot, "sendPhoto", params)
     }
 }
 
+finisher!(SendPhoto, Message);
+
 basesendtype!(SendAudio,
               "`Audio`",
-              [audio => String],
+              [audio => InputFile],
               [duration => (duration, Integer, "Set the duration of the track"),
                performer => (performer, String, "Set the performer of the track"),
                title => (title, String, "Set the title of the track"),
                reply_to_message_id => (reply_id, Integer, "Set a message ID to reply with this message."),
-               reply_markup => (markup, ReplyMarkup, "Set a `ReplyMarkup` to send, but instead of this, use the `keyboard`, `hide` or `force` methods")]);
+               reply_markup => (markup, Markup, "Set a `Markup` to send, but instead of this, use the `keyboard`, `hide` or `force` methods")]);
 
 addkeyboardfuncs!(SendAudio, reply_markup);
 
-impl Finisher<Message> for SendAudio {
-    fn end(&mut self) -> Result<Message> {
-        self.send.bot.send_audio(
-            self.send.chat_id,
-            self.audio.clone(),
-            self.duration,
-            self.performer.clone(),
-            self.title.clone(),
-            self.reply_to_message_id,
-            self.reply_markup.clone(),
-        )
+impl SendAudio {
+    fn request(&self) -> (&Client, &'static str, Params) {
+        let params = Params::new()
+            .add("chat_id", self.send.chat_id)
+            .file("audio", self.audio.clone())
+            .opt("duration", self.duration)
+            .opt("performer", self.performer.clone())
+            .opt("title", self.title.clone())
+            .opt("reply_to_message_id", self.reply_to_message_id)
+            .opt("reply_markup", self.reply_markup.clone());
+        (&self.send.bot, "sendAudio", params)
     }
 }
 
+finisher!(SendAudio, Message);
+
 basesendtype!(SendVoice,
               "`Voice`",
-              [voice => String],
+              [voice => InputFile],
               [duration => (duration, Integer, "Set the duration of the voice audio."),
                reply_to_message_id => (reply_id, Integer, "Set a message ID to reply with this message."),
-               reply_markup => (markup, ReplyMarkup, "Set a `ReplyMarkup` to send, but instead of this, use the `keyboard`, `hide` or `force` methods")]);
+               reply_markup => (markup, Markup, "Set a `Markup` to send, but instead of this, use the `keyboard`, `hide` or `force` methods")]);
 
 addkeyboardfuncs!(SendVoice, reply_markup);
 
-impl Finisher<Message> for SendVoice {
-    fn end(&mut self) -> Result<Message> {
-        self.send.bot.send_voice(
-            self.send.chat_id,
-            self.voice.clone(),
-            self.duration,
-            self.reply_to_message_id,
-            self.reply_markup.clone(),
-        )
+impl SendVoice {
+    fn request(&self) -> (&Client, &'static str, Params) {
+        let params = Params::new()
+            .add("chat_id", self.send.chat_id)
+            .file("voice", self.voice.clone())
+            .opt("duration", self.duration)
+            .opt("reply_to_message_id", self.reply_to_message_id)
+            .opt("reply_markup", self.reply_markup.clone());
+        (&self.send.bot, "sendVoice", params)
     }
 }
 
+finisher!(SendVoice, Message);
+
 basesendtype!(SendDocument,
               "`Document`",
-              [document => String],
+              [document => InputFile],
               [reply_to_message_id => (reply_id, Integer, "Set a message ID to reply with this message."),
-               reply_markup => (markup, ReplyMarkup, "Set a `ReplyMarkup` to send, but instead of this, use the `keyboard`, `hide` or `force` methods")]);
+               reply_markup => (markup, Markup, "Set a `Markup` to send, but instead of this, use the `keyboard`, `hide` or `force` methods")]);
 
 addkeyboardfuncs!(SendDocument, reply_markup);
 
-impl Finisher<Message> for SendDocument {
-    fn end(&mut self) -> Result<Message> {
-        self.send.bot.send_document(
-            self.send.chat_id,
-            self.document.clone(),
-            self.reply_to_message_id,
-            self.reply_markup.clone(),
-        )
+impl SendDocument {
+    fn request(&self) -> (&Client, &'static str, Params) {
+        let params = Params::new()
+            .add("chat_id", self.send.chat_id)
+            .file("document", self.document.clone())
+            .opt("reply_to_message_id", self.reply_to_message_id)
+            .opt("reply_markup", self.reply_markup.clone());
+        (&self.send.bot, "sendDocument", params)
     }
 }
 
+finisher!(SendDocument, Message);
+
 basesendtype!(SendSticker,
               "`Sticker`",
-              [sticker => String],
+              [sticker => InputFile],
               [reply_to_message_id => (reply_id, Integer, "Set a message ID to reply with this message."),
-               reply_markup => (markup, ReplyMarkup, "Set a `ReplyMarkup` to send, but instead of this, use the `keyboard`, `hide` or `force` methods")]);
+               reply_markup => (markup, Markup, "Set a `Markup` to send, but instead of this, use the `keyboard`, `hide` or `force` methods")]);
 
 addkeyboardfuncs!(SendSticker, reply_markup);
 
-impl Finisher<Message> for SendSticker {
-    fn end(&mut self) -> Result<Message> {
-        self.send.bot.send_sticker(
-            self.send.chat_id,
-            self.sticker.clone(),
-            self.reply_to_message_id,
-            self.reply_markup.clone(),
-        )
+impl SendSticker {
+    fn request(&self) -> (&Client, &'static str, Params) {
+        let params = Params::new()
+            .add("chat_id", self.send.chat_id)
+            .file("sticker", self.sticker.clone())
+            .opt("reply_to_message_id", self.reply_to_message_id)
+            .opt("reply_markup", self.reply_markup.clone());
+        (&self.send.bot, "sendSticker", params)
     }
 }
 
+finisher!(SendSticker, Message);
+
 basesendtype!(SendVideo,
               "`Video`",
-              [video => String],
+              [video => InputFile],
               [caption => (caption, String, "Set a caption to be included with the message."),
                duration => (duration, Integer, "Set the duration of the video"),
                reply_to_message_id => (reply_id, Integer, "Set a message ID to reply with this message."),
-               reply_markup => (markup, ReplyMarkup, "Set a `ReplyMarkup` to send, but instead of this, use the `keyboard`, `hide` or `force` methods")]);
+               reply_markup => (markup, Markup, "Set a `Markup` to send, but instead of this, use the `keyboard`, `hide` or `force` methods")]);
 
 addkeyboardfuncs!(SendVideo, reply_markup);
 
-impl Finisher<Message> for SendVideo {
-    fn end(&mut self) -> Result<Message> {
-        self.send.bot.send_video(
-            self.send.chat_id,
-            self.video.clone(),
-            self.caption.clone(),
-            self.duration,
-            self.reply_to_message_id,
-            self.reply_markup.clone(),
-        )
+impl SendVideo {
+    fn request(&self) -> (&Client, &'static str, Params) {
+        let params = Params::new()
+            .add("chat_id", self.send.chat_id)
+            .file("video", self.video.clone())
+            .opt("caption", self.caption.clone())
+            .opt("duration", self.duration)
+            .opt("reply_to_message_id", self.reply_to_message_id)
+            .opt("reply_markup", self.reply_markup.clone());
+        (&self.send.bot, "sendVideo", params)
     }
 }
 
+finisher!(SendVideo, Message);
+
 basesendtype!(SendForward,
               "`Forward`",
               [to => Integer, msg => Integer],
               []);
 
-impl Finisher<Message> for SendForward {
-    fn end(&mut self) -> Result<Message> {
-        self.send
-            .bot
-            .forward_message(self.send.chat_id, self.to, self.msg)
+impl SendForward {
+    fn request(&self) -> (&Client, &'static str, Params) {
+        let params = Params::new()
+            .add("chat_id", self.send.chat_id)
+            .add("from_chat_id", self.to)
+            .add("message_id", self.msg);
+        (&self.send.bot, "forwardMessage", params)
     }
 }
 
+finisher!(SendForward, Message);
+
 basesendtype!(SendAction,
               "`Action`",
               [action => ChatAction],
               []);
 
-impl Finisher<bool> for SendAction {
-    fn end(&mut self) -> Result<bool> {
-        self.send
-            .bot
-            .send_chat_action(self.send.chat_id, self.action)
+impl SendAction {
+    fn request(&self) -> (&Client, &'static str, Params) {
+        let params = Params::new()
+            .add("chat_id", self.send.chat_id)
+            .add("action", self.action.to_string());
+        (&self.send.bot, "sendChatAction", params)
     }
 }
 
+finisher!(SendAction, bool);
+
 basesendtype!(SendLocation,
               "`Location`",
               [latitude => Float,
                longitude => Float],
               [reply_to_message_id => (reply_id, Integer, "Set a message ID to reply with this message."),
-               reply_markup => (markup, ReplyMarkup, "Set a `ReplyMarkup` to send, but instead of this, use the `keyboard`, `hide` or `force` methods")]);
+               reply_markup => (markup, Markup, "Set a `Markup` to send, but instead of this, use the `keyboard`, `hide` or `force` methods")]);
 
 addkeyboardfuncs!(SendLocation, reply_markup);
 
-impl Finisher<Message> for SendLocation {
-    fn end(&mut self) -> Result<Message> {
-        self.send.bot.send_location(
-            self.send.chat_id,
-            self.latitude,
-            self.longitude,
-            self.reply_to_message_id,
-            self.reply_markup.clone(),
-        )
+impl SendLocation {
+    fn request(&self) -> (&Client, &'static str, Params) {
+        let params = Params::new()
+            .add("chat_id", self.send.chat_id)
+            .add("latitude", self.latitude)
+            .add("longitude", self.longitude)
+            .opt("reply_to_message_id", self.reply_to_message_id)
+            .opt("reply_markup", self.reply_markup.clone());
+        (&self.send.bot, "sendLocation", params)
     }
 }
+
+finisher!(SendLocation, Message);
+
+/// Struct built by `AwesomeBot::answer_callback` to answer a `CallbackQuery`.
+///
+/// Telegram shows a progress bar in the button until the query is answered,
+/// so always answer them, even without text.
+pub struct AnswerCallback {
+    bot: Client,
+    callback_query_id: String,
+    text: Option<String>,
+    show_alert: Option<bool>,
+    url: Option<String>,
+    cache_time: Option<Integer>,
+}
+
+impl AnswerCallback {
+    pub(crate) fn new(bot: Client, id: &str) -> AnswerCallback {
+        AnswerCallback {
+            bot: bot,
+            callback_query_id: id.to_owned(),
+            text: None,
+            show_alert: None,
+            url: None,
+            cache_time: None,
+        }
+    }
+
+    /// Set a text to show to the user.
+    pub fn text(&mut self, t: &str) -> &mut AnswerCallback {
+        self.text = Some(t.to_owned());
+        self
+    }
+
+    /// Set `true` to show the text as an alert instead of a notification.
+    pub fn alert(&mut self, a: bool) -> &mut AnswerCallback {
+        self.show_alert = Some(a);
+        self
+    }
+
+    /// Set an URL to open, only for game URLs or `t.me` links with a parameter for the bot.
+    pub fn url(&mut self, u: &str) -> &mut AnswerCallback {
+        self.url = Some(u.to_owned());
+        self
+    }
+
+    /// Set the time in seconds that the answer may be cached by the client.
+    pub fn cache_time(&mut self, seconds: Integer) -> &mut AnswerCallback {
+        self.cache_time = Some(seconds);
+        self
+    }
+}
+
+impl AnswerCallback {
+    fn request(&self) -> (&Client, &'static str, Params) {
+        let params = Params::new()
+            .add("callback_query_id", self.callback_query_id.clone())
+            .opt("text", self.text.clone())
+            .opt("show_alert", self.show_alert)
+            .opt("url", self.url.clone())
+            .opt("cache_time", self.cache_time);
+        (&self.bot, "answerCallbackQuery", params)
+    }
+}
+
+finisher!(AnswerCallback, bool);
+
+/// Struct built by `AwesomeBot::answer_inline` to answer an `InlineQuery`.
+pub struct AnswerInlineQuery {
+    bot: Client,
+    inline_query_id: String,
+    results: Vec<InlineQueryResult>,
+    cache_time: Option<Integer>,
+    is_personal: Option<bool>,
+    next_offset: Option<String>,
+}
+
+impl AnswerInlineQuery {
+    pub(crate) fn new(bot: Client, id: &str) -> AnswerInlineQuery {
+        AnswerInlineQuery {
+            bot: bot,
+            inline_query_id: id.to_owned(),
+            results: Vec::new(),
+            cache_time: None,
+            is_personal: None,
+            next_offset: None,
+        }
+    }
+
+    /// Add a result.
+    pub fn result(&mut self, r: InlineQueryResult) -> &mut AnswerInlineQuery {
+        self.results.push(r);
+        self
+    }
+
+    /// Add an article result, that sends the text `text` when chosen.
+    pub fn article(&mut self, id: &str, title: &str, text: &str) -> &mut AnswerInlineQuery {
+        self.result(InlineQueryResult::Article {
+            id: id.to_owned(),
+            title: title.to_owned(),
+            text: text.to_owned(),
+            description: None,
+            url: None,
+            thumb_url: None,
+        })
+    }
+
+    /// Add a photo result, `photo_url` must be a JPEG image.
+    pub fn photo(&mut self, id: &str, photo_url: &str, thumb_url: &str) -> &mut AnswerInlineQuery {
+        self.result(InlineQueryResult::Photo {
+            id: id.to_owned(),
+            photo_url: photo_url.to_owned(),
+            thumb_url: thumb_url.to_owned(),
+            title: None,
+            caption: None,
+        })
+    }
+
+    /// Add a document result, `mime_type` can be `application/pdf` or `application/zip`.
+    pub fn document(
+        &mut self,
+        id: &str,
+        title: &str,
+        document_url: &str,
+        mime_type: &str,
+    ) -> &mut AnswerInlineQuery {
+        self.result(InlineQueryResult::Document {
+            id: id.to_owned(),
+            title: title.to_owned(),
+            document_url: document_url.to_owned(),
+            mime_type: mime_type.to_owned(),
+            description: None,
+            caption: None,
+        })
+    }
+
+    /// Set the time in seconds that the results may be cached in the server.
+    pub fn cache_time(&mut self, seconds: Integer) -> &mut AnswerInlineQuery {
+        self.cache_time = Some(seconds);
+        self
+    }
+
+    /// Set `true` to cache the results only for the user that made the query.
+    pub fn personal(&mut self, p: bool) -> &mut AnswerInlineQuery {
+        self.is_personal = Some(p);
+        self
+    }
+
+    /// Set the offset that the client will send in the next query to get more results.
+    pub fn next_offset(&mut self, offset: &str) -> &mut AnswerInlineQuery {
+        self.next_offset = Some(offset.to_owned());
+        self
+    }
+}
+
+impl AnswerInlineQuery {
+    fn request(&self) -> (&Client, &'static str, Params) {
+        let params = Params::new()
+            .add("inline_query_id", self.inline_query_id.clone())
+            .add("results", self.results.clone())
+            .opt("cache_time", self.cache_time)
+            .opt("is_personal", self.is_personal)
+            .opt("next_offset", self.next_offset.clone());
+        (&self.bot, "answerInlineQuery", params)
+    }
+}
+
+finisher!(AnswerInlineQuery, bool);
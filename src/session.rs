@@ -0,0 +1,304 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use rustc_serialize::json::{self, Json};
+use rustc_serialize::{Decodable, Encodable};
+use telegram_bot::{Chat, Integer, Message};
+
+/// Storage of the session data of the users, the values saved by each user in each chat.
+///
+/// The handlers use it through a `Session`, created with `AwesomeBot::session`.
+/// The stores are used from all the threads of the bot at the same time.
+pub trait SessionStore: Send + Sync {
+    /// Get the value `key` of the user in the chat, `None` if there isn't any.
+    fn get(&self, chat: Integer, user: Integer, key: &str) -> io::Result<Option<Json>>;
+    /// Set the value `key` of the user in the chat.
+    fn set(&self, chat: Integer, user: Integer, key: &str, value: Json) -> io::Result<()>;
+    /// Delete the value `key` of the user in the chat.
+    fn delete(&self, chat: Integer, user: Integer, key: &str) -> io::Result<()>;
+}
+
+// The sessions are kept in a JSON object, with an object for each "chat:user"
+fn session_key(chat: Integer, user: Integer) -> String {
+    format!("{}:{}", chat, user)
+}
+
+fn get_value(data: &json::Object, chat: Integer, user: Integer, key: &str) -> Option<Json> {
+    data.get(&session_key(chat, user))
+        .and_then(|s| s.find(key))
+        .cloned()
+}
+
+fn set_value(data: &mut json::Object, chat: Integer, user: Integer, key: &str, value: Json) {
+    let session = data.entry(session_key(chat, user))
+        .or_insert_with(|| Json::Object(json::Object::new()));
+    if let Json::Object(ref mut obj) = *session {
+        obj.insert(key.to_owned(), value);
+    }
+}
+
+fn delete_value(data: &mut json::Object, chat: Integer, user: Integer, key: &str) {
+    let skey = session_key(chat, user);
+    let empty = match data.get_mut(&skey) {
+        Some(&mut Json::Object(ref mut obj)) => {
+            obj.remove(key);
+            obj.is_empty()
+        }
+        _ => false,
+    };
+    if empty {
+        data.remove(&skey);
+    }
+}
+
+/// `SessionStore` that keeps the sessions in memory, they are lost when the bot stops.
+/// This is the default store.
+#[derive(Default)]
+pub struct MemorySessionStore {
+    data: Mutex<json::Object>,
+}
+
+impl MemorySessionStore {
+    pub fn new() -> MemorySessionStore {
+        MemorySessionStore::default()
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    fn get(&self, chat: Integer, user: Integer, key: &str) -> io::Result<Option<Json>> {
+        Ok(get_value(&self.data.lock().unwrap(), chat, user, key))
+    }
+
+    fn set(&self, chat: Integer, user: Integer, key: &str, value: Json) -> io::Result<()> {
+        set_value(&mut self.data.lock().unwrap(), chat, user, key, value);
+        Ok(())
+    }
+
+    fn delete(&self, chat: Integer, user: Integer, key: &str) -> io::Result<()> {
+        delete_value(&mut self.data.lock().unwrap(), chat, user, key);
+        Ok(())
+    }
+}
+
+/// `SessionStore` that keeps the sessions in a JSON file, so they survive restarts.
+///
+/// The sessions are kept in memory too, and the whole file is written on each change,
+/// so it's meant for small bots.
+pub struct JsonFileSessionStore {
+    path: PathBuf,
+    // Loaded the first time that it's used
+    data: Mutex<Option<json::Object>>,
+}
+
+impl JsonFileSessionStore {
+    /// Create a store that uses the file in `path`, it's created when saving the first time.
+    pub fn new<P: AsRef<Path>>(path: P) -> JsonFileSessionStore {
+        JsonFileSessionStore {
+            path: path.as_ref().to_path_buf(),
+            data: Mutex::new(None),
+        }
+    }
+
+    // Run `f` with the sessions, loading them if they aren't loaded yet.
+    // If `save` is true, the file is written after running `f`.
+    fn with_data<T, F>(&self, save: bool, f: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut json::Object) -> T,
+    {
+        let mut data = self.data.lock().unwrap();
+        if data.is_none() {
+            *data = Some(try!(self.load()));
+        }
+        let data = data.as_mut().unwrap();
+        let result = f(data);
+        if save {
+            try!(self.save(data));
+        }
+        Ok(result)
+    }
+
+    fn load(&self) -> io::Result<json::Object> {
+        let mut content = String::new();
+        match fs::File::open(&self.path) {
+            Ok(mut f) => try!(f.read_to_string(&mut content)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(json::Object::new()),
+            Err(e) => return Err(e),
+        };
+        match Json::from_str(&content) {
+            Ok(Json::Object(obj)) => Ok(obj),
+            Ok(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "The sessions file isn't a JSON object",
+            )),
+            Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        }
+    }
+
+    fn save(&self, data: &json::Object) -> io::Result<()> {
+        // Write and rename, so a crash never leaves a half written file
+        let tmp = self.path.with_extension("tmp");
+        {
+            let mut f = try!(fs::File::create(&tmp));
+            try!(f.write_all(Json::Object(data.clone()).to_string().as_bytes()));
+            try!(f.sync_all());
+        }
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+impl SessionStore for JsonFileSessionStore {
+    fn get(&self, chat: Integer, user: Integer, key: &str) -> io::Result<Option<Json>> {
+        self.with_data(false, |data| get_value(data, chat, user, key))
+    }
+
+    fn set(&self, chat: Integer, user: Integer, key: &str, value: Json) -> io::Result<()> {
+        self.with_data(true, |data| set_value(data, chat, user, key, value))
+    }
+
+    fn delete(&self, chat: Integer, user: Integer, key: &str) -> io::Result<()> {
+        self.with_data(true, |data| delete_value(data, chat, user, key))
+    }
+}
+
+/// The session of a user in a chat, created by `AwesomeBot::session` (or
+/// `AwesomeBot::update_session` inside any handler) to keep data between messages.
+/// Any type that can be encoded with `rustc_serialize` can be saved.
+///
+/// The channel posts don't have a real sender, so their session is the one of the channel,
+/// shared by everyone that posts in it.
+///
+/// ``` ignore
+/// fn count(bot: &AwesomeBot, msg: &Message, _: String) -> Result<(), Box<Error + Send + Sync>> {
+///     let session = bot.session(msg);
+///     let n: u32 = try!(session.get("count")).unwrap_or(0) + 1;
+///     try!(session.set("count", &n));
+///     try!(bot.answer(msg).text(&format!("You have counted {} times", n)).end());
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Session {
+    store: Arc<SessionStore>,
+    chat: Integer,
+    user: Integer,
+}
+
+impl Session {
+    pub(crate) fn new(store: Arc<SessionStore>, msg: &Message) -> Session {
+        // The sender of the channel posts is made up, use the channel itself
+        let user = match msg.chat {
+            Chat::Channel { .. } => msg.chat.id(),
+            _ => msg.from.id,
+        };
+        Session::with_key(store, msg.chat.id(), user)
+    }
+
+    pub(crate) fn with_key(store: Arc<SessionStore>, chat: Integer, user: Integer) -> Session {
+        Session {
+            store: store,
+            chat: chat,
+            user: user,
+        }
+    }
+
+    /// Get the value `key`, `None` if there isn't any.
+    /// It fails if the value saved can't be decoded as `T`.
+    pub fn get<T: Decodable>(&self, key: &str) -> io::Result<Option<T>> {
+        match try!(self.store.get(self.chat, self.user, key)) {
+            Some(value) => {
+                let mut decoder = json::Decoder::new(value);
+                Decodable::decode(&mut decoder)
+                    .map(Some)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Set the value `key`.
+    pub fn set<T: Encodable>(&self, key: &str, value: &T) -> io::Result<()> {
+        let encoded = try!(
+            json::encode(value).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+        );
+        let value = try!(
+            Json::from_str(&encoded).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+        );
+        self.store.set(self.chat, self.user, key, value)
+    }
+
+    /// Delete the value `key`.
+    pub fn delete(&self, key: &str) -> io::Result<()> {
+        self.store.delete(self.chat, self.user, key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{JsonFileSessionStore, MemorySessionStore, SessionStore};
+    use builder::UpdateKind;
+    use rustc_serialize::json::ToJson;
+    use std::fs;
+    use test::fixtures::{temp_path, test_bot, text_update, update};
+
+    #[test]
+    fn session_stores_right() {
+        let path = temp_path("sessions.json");
+        let memory = MemorySessionStore::new();
+        let file = JsonFileSessionStore::new(&path);
+        let stores: [&SessionStore; 2] = [&memory, &file];
+        for store in &stores {
+            assert_eq!(store.get(1, 2, "count").unwrap(), None);
+            store.set(1, 2, "count", 3u32.to_json()).unwrap();
+            store.set(1, 3, "count", 4u32.to_json()).unwrap();
+            assert_eq!(store.get(1, 2, "count").unwrap(), Some(3u32.to_json()));
+            assert_eq!(store.get(1, 3, "count").unwrap(), Some(4u32.to_json()));
+            store.delete(1, 2, "count").unwrap();
+            assert_eq!(store.get(1, 2, "count").unwrap(), None);
+        }
+        let reopened = JsonFileSessionStore::new(&path);
+        assert_eq!(reopened.get(1, 3, "count").unwrap(), Some(4u32.to_json()));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn update_session_right() {
+        let mut bot = test_bot();
+        assert!(bot.update_session().is_none());
+        bot.any_fn(|bot, _| bot.update_session().unwrap().set("seen", &"message").unwrap())
+            .update_kinds(&[UpdateKind::Message, UpdateKind::ChannelPost]);
+        bot.callback_query_fn(|bot, _| {
+            bot.update_session().unwrap().set("seen", &"callback").unwrap()
+        });
+        bot.inline_query_fn(|bot, _| bot.update_session().unwrap().set("seen", &"inline").unwrap());
+
+        bot.handle_update(text_update("Hi"));
+        bot.handle_update(update(
+            r#"{"update_id": 2, "channel_post": {"message_id": 6, "date": 0, "text": "Hi",
+                "chat": {"id": -100, "type": "channel", "title": "News"}}}"#,
+        ));
+        bot.handle_update(update(
+            r#"{"update_id": 3, "callback_query": {"id": "q", "data": "a",
+                "from": {"id": 3, "first_name": "B"},
+                "message": {"message_id": 7, "date": 0, "text": "Press",
+                    "from": {"id": 1234, "first_name": "Bot"},
+                    "chat": {"id": 1, "type": "private", "first_name": "A"}}}}"#,
+        ));
+        bot.handle_update(update(
+            r#"{"update_id": 4, "inline_query": {"id": "i", "query": "", "offset": "",
+                "from": {"id": 4, "first_name": "C"}}}"#,
+        ));
+
+        // The message sender in its chat, the channel, the user that pressed the button
+        // in the chat of the message, and the user in its private chat
+        for &(chat, user, seen) in &[
+            (1, 2, "message"),
+            (-100, -100, "message"),
+            (1, 3, "callback"),
+            (4, 4, "inline"),
+        ] {
+            assert_eq!(bot.sessions.get(chat, user, "seen").unwrap(), Some(seen.to_json()));
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use ctrlc;
+use telegram_bot::Integer;
+
+struct ShutdownState {
+    stop: AtomicBool,
+    offset: Mutex<Option<Integer>>,
+}
+
+/// Handle to stop a running bot, get it with `AwesomeBot::shutdown_handle`.
+///
+/// It can be cloned and sent to other threads (or used inside a handler). When `shutdown`
+/// is called, the bot stops receiving updates (after the poll running, that can take up to
+/// the `poll_timeout` option, when no update arrives), waits for the handlers that are
+/// running, confirms to Telegram the updates handled, and `simple_start`
+/// (or `webhook_start`) returns `Ok(())`.
+///
+/// The handlers still running after the `drain_timeout` option are not stopped, they keep
+/// running in the background while `simple_start` returns. Their updates aren't confirmed,
+/// so they are received again the next time the bot starts, if Telegram still has them
+/// (check `AwesomeBot::start_with`).
+///
+/// Starting the bot again clears the shutdown, so a `shutdown` called before starting
+/// has no effect.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    state: Arc<ShutdownState>,
+}
+
+impl ShutdownHandle {
+    pub(crate) fn new() -> ShutdownHandle {
+        ShutdownHandle {
+            state: Arc::new(ShutdownState {
+                stop: AtomicBool::new(false),
+                offset: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Ask the bot to stop.
+    pub fn shutdown(&self) {
+        self.state.stop.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns `true` if the bot has been asked to stop.
+    pub fn is_shutdown(&self) -> bool {
+        self.state.stop.load(Ordering::SeqCst)
+    }
+
+    /// The offset of the next update to receive, that is, the last confirmed update plus one.
+    /// It's available once the bot has stopped, pass it to the `offset` option to continue
+    /// from there.
+    pub fn last_offset(&self) -> Option<Integer> {
+        *self.state.offset.lock().unwrap()
+    }
+
+    /// Call `shutdown` when the process receives SIGINT or SIGTERM (Ctrl-C in Windows).
+    ///
+    /// Only one signal handler can be set by process, so this fails if it's called twice.
+    pub fn shutdown_on_signals(&self) -> io::Result<()> {
+        let handle = self.clone();
+        ctrlc::set_handler(move || handle.shutdown())
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))
+    }
+
+    // Called when the bot starts, so it can be stopped and started again
+    pub(crate) fn reset(&self) {
+        self.state.stop.store(false, Ordering::SeqCst);
+    }
+
+    pub(crate) fn set_offset(&self, offset: Integer) {
+        *self.state.offset.lock().unwrap() = Some(offset);
+    }
+}
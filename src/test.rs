@@ -1,6 +1,156 @@
+// Helpers shared by the tests of all the modules
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use api::{Client, DEFAULT_API_URL, DEFAULT_DOWNLOAD_LIMIT};
+    use hyper;
+    use hyper::header::Headers;
+    use hyper::server::{Listening, Request, Response, Server};
+    use hyper::status::StatusCode;
+    use hyper::uri::RequestUri;
+    use queue::RateLimits;
+    use retry::RetryPolicy;
+    use rustc_serialize::json::{Json, ToJson};
+    use std::env;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::process;
+    use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+    use update::{decode_update, Update};
+    use AwesomeBot;
+
+    static TEMP_FILES: AtomicUsize = ATOMIC_USIZE_INIT;
+
+    // A bot that isn't connected, to test the routes
+    pub fn test_bot() -> AwesomeBot {
+        api_bot(DEFAULT_API_URL)
+    }
+
+    // A bot that uses the Bot API server in `url`, usually a `fake_api`
+    pub fn api_bot(url: &str) -> AwesomeBot {
+        let api = Client::new(
+            url,
+            "1234:token",
+            DEFAULT_DOWNLOAD_LIMIT,
+            RateLimits::default(),
+            RetryPolicy::default(),
+        );
+        AwesomeBot::with_api(api, 1234, String::from("usernamebot"))
+    }
+
+    // The JSON of an update with a text message of the user 2 in the private chat 1
+    pub fn text_update_json(update_id: i64, text: &str) -> String {
+        format!(
+            r#"{{"update_id": {}, "message": {{"message_id": 5, "date": 0,
+                "from": {{"id": 2, "first_name": "A"}},
+                "chat": {{"id": 1, "type": "private", "first_name": "A"}}, "text": {}}}}}"#,
+            update_id,
+            text.to_json()
+        )
+    }
+
+    // An update with a text message of the user 2 in the private chat 1
+    pub fn text_update(text: &str) -> Update {
+        update(&text_update_json(1, text))
+    }
+
+    // Decode the JSON of an update
+    pub fn update(json: &str) -> Update {
+        decode_update(Json::from_str(json).unwrap()).unwrap()
+    }
+
+    // A path in the temporary directory that no other test uses, even running
+    // the tests of different processes at the same time
+    pub fn temp_path(name: &str) -> PathBuf {
+        let n = TEMP_FILES.fetch_add(1, Ordering::SeqCst);
+        env::temp_dir().join(format!("awesome_bot_{}_{}_{}", process::id(), n, name))
+    }
+
+    // A successful response of the Bot API
+    pub fn ok(result: &str) -> String {
+        format!(r#"{{"ok": true, "result": {}}}"#, result)
+    }
+
+    // A fake Bot API server listening in a free port. `answer` receives the last part of
+    // the path (the method called, or the name of the file downloaded) and the JSON body,
+    // and returns the status and the body of the response.
+    // Returns the server, to close it, and its URL.
+    pub fn fake_http<F>(answer: F) -> (Listening, String)
+    where
+        F: Fn(&str, &Json) -> (StatusCode, String) + Send + Sync + 'static,
+    {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let listening = server
+            .handle(move |mut req: Request, mut res: Response| {
+                let method = match req.uri {
+                    RequestUri::AbsolutePath(ref p) => {
+                        p.rsplit('/').next().unwrap_or("").to_owned()
+                    }
+                    _ => String::new(),
+                };
+                let mut body = String::new();
+                let _ = req.read_to_string(&mut body);
+                let params = Json::from_str(&body).unwrap_or(Json::Null);
+                let (status, body) = answer(&method, &params);
+                *res.status_mut() = status;
+                let _ = res.send(body.as_bytes());
+            })
+            .unwrap();
+        let url = format!("http://{}", listening.socket);
+        (listening, url)
+    }
+
+    // POST `body` to `url`, like Telegram does with the webhooks, and return the status
+    pub fn post(url: &str, secret: Option<&str>, body: &str) -> StatusCode {
+        let mut headers = Headers::new();
+        if let Some(s) = secret {
+            headers.set_raw("X-Telegram-Bot-Api-Secret-Token", vec![s.as_bytes().to_vec()]);
+        }
+        hyper::Client::new()
+            .post(url)
+            .headers(headers)
+            .body(body)
+            .send()
+            .unwrap()
+            .status
+    }
+
+    // An address with a port that is free now, for the servers that don't return theirs
+    pub fn free_addr() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    }
+
+    // The same as `fake_http`, but always answering with status 200
+    pub fn fake_api<F>(answer: F) -> (Listening, String)
+    where
+        F: Fn(&str, &Json) -> String + Send + Sync + 'static,
+    {
+        fake_http(move |method, params| (StatusCode::Ok, answer(method, params)))
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use builder::{StartOptions, UpdateKind};
+    use dispatch::DispatchPolicy;
+    use error::DownloadError;
+    use handler::RouteId;
+    use hyper::status::StatusCode;
     use regex::Regex;
+    use rustc_serialize::json::{Json, ToJson};
+    use send::Finisher;
+    use shutdown::ShutdownHandle;
+    use std::cmp;
+    use std::fs;
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+    use telegram_bot::{Error, Message, PhotoSize, Result};
+    use test::fixtures::{api_bot, fake_api, fake_http, free_addr, ok, post, temp_path, test_bot,
+                         text_update, text_update_json, update};
     use AwesomeBot;
 
     struct Defs {
@@ -82,4 +232,447 @@ mod test {
         assert_eq!(cap.len(), 1);
         assert_eq!(cap.at(0), Some("/test@usernamebot"));
     }
+
+    #[test]
+    fn start_polls_while_handling_right() {
+        let shutdown: Arc<Mutex<Option<ShutdownHandle>>> = Arc::new(Mutex::new(None));
+        let handle = shutdown.clone();
+        let (mut listening, url) = fake_api(move |_, params| {
+            let offset = params.find("offset").and_then(|o| o.as_i64()).unwrap_or(0);
+            if offset > 110 {
+                if let Some(ref h) = *handle.lock().unwrap() {
+                    h.shutdown();
+                }
+            }
+            // Telegram keeps the updates until an offset after them is asked,
+            // and gives 100 of them at most
+            let updates: Vec<String> = (cmp::max(offset, 10)..111)
+                .take(100)
+                .map(|id| text_update_json(id, if id == 10 { "slow" } else { "fast" }))
+                .collect();
+            ok(&format!("[{}]", updates.join(",")))
+        });
+
+        let fast = Arc::new(AtomicUsize::new(0));
+        let slow = Arc::new(Mutex::new(Vec::new()));
+        let mut bot = api_bot(&url);
+        let (log, count) = (slow.clone(), fast.clone());
+        bot.simple_regex("^slow$", move |_, _, _| {
+            // The 100 updates after this one are received and handled meanwhile
+            for _ in 0..100 {
+                if count.load(Ordering::SeqCst) == 100 {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(20));
+            }
+            log.lock().unwrap().push(count.load(Ordering::SeqCst));
+        });
+        let count = fast.clone();
+        bot.simple_regex("^fast$", move |_, _, _| {
+            count.fetch_add(1, Ordering::SeqCst);
+        });
+        *shutdown.lock().unwrap() = Some(bot.shutdown_handle());
+
+        bot.simple_start().unwrap();
+        listening.close().unwrap();
+
+        // Each update is handled once, and the slow one doesn't stop the new ones
+        assert_eq!(*slow.lock().unwrap(), vec![100]);
+        assert_eq!(fast.load(Ordering::SeqCst), 100);
+        assert_eq!(bot.shutdown_handle().last_offset(), Some(111));
+    }
+
+    #[test]
+    fn start_again_right() {
+        let shutdown: Arc<Mutex<Option<ShutdownHandle>>> = Arc::new(Mutex::new(None));
+        let timeouts = Arc::new(Mutex::new(Vec::new()));
+        let (handle, log) = (shutdown.clone(), timeouts.clone());
+        let (mut listening, url) = fake_api(move |method, params| {
+            if method == "getUpdates" {
+                log.lock().unwrap().push(params.find("timeout").and_then(|t| t.as_i64()));
+                if let Some(ref h) = *handle.lock().unwrap() {
+                    h.shutdown();
+                }
+            }
+            ok("[]")
+        });
+
+        let bot = api_bot(&url);
+        *shutdown.lock().unwrap() = Some(bot.shutdown_handle());
+        let mut options = StartOptions::default();
+        options.poll_timeout = 60;
+        // The shutdown of the first start doesn't stop the second one at once
+        bot.start_with(options.clone()).unwrap();
+        bot.start_with(options).unwrap();
+        listening.close().unwrap();
+
+        // The polls use the timeout given
+        assert_eq!(*timeouts.lock().unwrap(), vec![Some(60), Some(60)]);
+    }
+
+    #[test]
+    fn start_pool_size_zero_right() {
+        let shutdown: Arc<Mutex<Option<ShutdownHandle>>> = Arc::new(Mutex::new(None));
+        let handle = shutdown.clone();
+        let (mut listening, url) = fake_api(move |_, params| {
+            let offset = params.find("offset").and_then(|o| o.as_i64()).unwrap_or(0);
+            if offset >= 11 {
+                if let Some(ref h) = *handle.lock().unwrap() {
+                    h.shutdown();
+                }
+                return ok("[]");
+            }
+            ok(&format!("[{}]", text_update_json(10, "Hi")))
+        });
+
+        let handled = Arc::new(AtomicUsize::new(0));
+        let mut bot = api_bot(&url);
+        let count = handled.clone();
+        bot.simple_regex("^Hi$", move |_, _, _| {
+            count.fetch_add(1, Ordering::SeqCst);
+        });
+        *shutdown.lock().unwrap() = Some(bot.shutdown_handle());
+        let mut options = StartOptions::default();
+        options.pool_size = 0;
+        // Runs the handlers in one thread instead of panicking
+        bot.start_with(options).unwrap();
+        listening.close().unwrap();
+
+        assert_eq!(handled.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn start_reports_invalid_updates_right() {
+        let shutdown: Arc<Mutex<Option<ShutdownHandle>>> = Arc::new(Mutex::new(None));
+        let handle = shutdown.clone();
+        let (mut listening, url) = fake_api(move |_, params| {
+            let offset = params.find("offset").and_then(|o| o.as_i64()).unwrap_or(0);
+            if offset >= 11 {
+                if let Some(ref h) = *handle.lock().unwrap() {
+                    h.shutdown();
+                }
+                return ok("[]");
+            }
+            ok(r#"[{"update_id": 10, "message": {"message_id": "five"}}]"#)
+        });
+
+        let invalid = Arc::new(Mutex::new(Vec::new()));
+        let mut bot = api_bot(&url);
+        let log = invalid.clone();
+        bot.on_invalid_update(move |_, u| log.lock().unwrap().push(u.update_id));
+        *shutdown.lock().unwrap() = Some(bot.shutdown_handle());
+
+        bot.simple_start().unwrap();
+        listening.close().unwrap();
+
+        // Reported once, and confirmed anyway
+        assert_eq!(*invalid.lock().unwrap(), vec![Some(10)]);
+        assert_eq!(bot.shutdown_handle().last_offset(), Some(11));
+    }
+
+    #[test]
+    fn webhook_start_right() {
+        let handled = Arc::new(Mutex::new(Vec::new()));
+        let mut bot = test_bot();
+        let log = handled.clone();
+        bot.simple_regex("^panic$", |_, _, _| -> () { panic!("oops") });
+        bot.simple_regex("^Hi$", move |_, _, text| log.lock().unwrap().push(text));
+        bot.on_panic(|_, _, _, _| ());
+        let shutdown = bot.shutdown_handle();
+
+        let addr = free_addr();
+        let server = {
+            let (bot, addr) = (bot.clone(), addr.clone());
+            thread::spawn(move || bot.webhook_start(&addr, "/hook", Some("secret")))
+        };
+        let url = format!("http://{}/hook?from=telegram", addr);
+        // Wait for the server to start
+        for _ in 0..50 {
+            if TcpStream::connect(&*addr).is_ok() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(post(&url, None, "{}"), StatusCode::Forbidden);
+        for &(id, text) in &[(1, "panic"), (2, "Hi")] {
+            let body = text_update_json(id, text);
+            assert_eq!(post(&url, Some("secret"), &body), StatusCode::Ok);
+        }
+        thread::sleep(Duration::from_millis(300));
+        shutdown.shutdown();
+        server.join().unwrap().unwrap();
+
+        assert_eq!(*handled.lock().unwrap(), vec!["Hi"]);
+        // The update that panicked is finished too
+        assert_eq!(shutdown.last_offset(), Some(3));
+        // The address is free to start again
+        assert!(TcpListener::bind(&*addr).is_ok());
+    }
+
+    #[test]
+    fn edits_and_channel_posts_routing_right() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut bot = test_bot();
+        let logger = |name: &'static str| {
+            let log = log.clone();
+            move |bot: &AwesomeBot, msg: &Message, _: String| {
+                log.lock().unwrap().push((name, bot.update_kind(), msg.from.id))
+            }
+        };
+        bot.simple_regex("^new$", logger("messages"));
+        bot.simple_regex(".*", logger("edits"))
+            .update_kinds(&[UpdateKind::EditedMessage, UpdateKind::EditedChannelPost]);
+        bot.simple_regex(".*", logger("posts"))
+            .update_kinds(&[UpdateKind::ChannelPost]);
+
+        let user = r#""from": {"id": 2, "first_name": "A"},
+                      "chat": {"id": 1, "type": "private", "first_name": "A"}"#;
+        let channel = r#""chat": {"id": -100, "type": "channel", "title": "News"}"#;
+        let updates = [
+            ("edited_message", user, "new"),
+            ("channel_post", channel, "new"),
+            ("edited_channel_post", channel, "new"),
+            ("message", user, "new"),
+        ];
+        for (id, &(kind, from, text)) in updates.iter().enumerate() {
+            bot.handle_update(update(&format!(
+                r#"{{"update_id": {}, "{}": {{"message_id": 5, "date": 0, {}, "text": "{}"}}}}"#,
+                id, kind, from, text
+            )));
+        }
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                ("edits", UpdateKind::EditedMessage, 2),
+                // The sender of the channel posts is the channel
+                ("posts", UpdateKind::ChannelPost, -100),
+                ("edits", UpdateKind::EditedChannelPost, -100),
+                ("messages", UpdateKind::Message, 2),
+            ]
+        );
+    }
+
+    // The `DownloadError` inside the error of a download
+    fn download_error<T>(result: Result<T>) -> Option<DownloadError> {
+        match result {
+            Err(Error::Io(ref e)) => e.get_ref()
+                .and_then(|e| e.downcast_ref::<DownloadError>())
+                .cloned(),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn download_right() {
+        let (mut listening, url) = fake_http(|method, params| {
+            let id = params.find("file_id").and_then(|i| i.as_string()).unwrap_or("");
+            match (method, id) {
+                ("getFile", "nopath") => (StatusCode::Ok, ok(r#"{"file_id": "nopath"}"#)),
+                ("getFile", _) => (
+                    StatusCode::Ok,
+                    ok(&format!(r#"{{"file_id": "{0}", "file_path": "docs/{0}.txt"}}"#, id)),
+                ),
+                ("a.txt", _) => (StatusCode::Ok, String::from("hello")),
+                _ => (StatusCode::NotFound, String::from("Not found")),
+            }
+        });
+        let bot = api_bot(&url);
+
+        assert_eq!(bot.download("a").unwrap(), b"hello".to_vec());
+        assert_eq!(download_error(bot.download("gone")), Some(DownloadError::Status(404)));
+        assert_eq!(download_error(bot.download("nopath")), Some(DownloadError::NoFilePath));
+        assert_eq!(
+            download_error(bot.download(&Vec::<PhotoSize>::new())),
+            Some(DownloadError::NoFile)
+        );
+
+        let path = temp_path("download.txt");
+        let part = path.with_extension("txt.part");
+        assert_eq!(bot.download_to("a", &path).unwrap(), 5);
+        assert_eq!(fs::read(&path).unwrap(), b"hello".to_vec());
+        assert!(!part.exists());
+        fs::remove_file(&path).unwrap();
+
+        // Nothing is left when it fails
+        assert!(bot.download_to("gone", &path).is_err());
+        assert!(!path.exists());
+        assert!(!part.exists());
+        listening.close().unwrap();
+    }
+
+    #[test]
+    fn callback_routing_right() {
+        let answers = Arc::new(Mutex::new(Vec::new()));
+        let log = answers.clone();
+        let (mut listening, url) = fake_api(move |method, params| {
+            log.lock().unwrap().push((method.to_owned(), params.clone()));
+            ok("true")
+        });
+
+        let votes = Arc::new(Mutex::new(Vec::new()));
+        let mut bot = api_bot(&url);
+        let log = votes.clone();
+        bot.callback("^vote (\\w+)$", move |bot, query, data, captures| {
+            let message = query.message.as_ref().map(|m| m.message_id);
+            log.lock().unwrap().push((data, captures[1].clone(), message));
+            bot.answer_callback(&query.id)
+                .text("Voted")
+                .alert(true)
+                .end()
+                .map(|_| ())
+        });
+        for &(id, data) in &[(1, "vote yes"), (2, "other")] {
+            bot.handle_update(update(&format!(
+                r#"{{"update_id": {}, "callback_query": {{"id": "q{}", "data": "{}",
+                    "from": {{"id": 2, "first_name": "A"}},
+                    "message": {{"message_id": 7, "date": 0, "text": "Vote",
+                        "from": {{"id": 1234, "first_name": "Bot"}},
+                        "chat": {{"id": 1, "type": "private", "first_name": "A"}}}}}}}}"#,
+                id, id, data
+            )));
+        }
+        listening.close().unwrap();
+
+        assert_eq!(
+            *votes.lock().unwrap(),
+            vec![(String::from("vote yes"), String::from("yes"), Some(7))]
+        );
+        let answers = answers.lock().unwrap();
+        assert_eq!(answers.len(), 1);
+        assert_eq!(answers[0].0, "answerCallbackQuery");
+        assert_eq!(answers[0].1.find("callback_query_id"), Some(&"q1".to_json()));
+        assert_eq!(answers[0].1.find("text"), Some(&"Voted".to_json()));
+        assert_eq!(answers[0].1.find("show_alert"), Some(&Json::Boolean(true)));
+    }
+
+    #[test]
+    fn inline_query_answer_right() {
+        let answers = Arc::new(Mutex::new(Vec::new()));
+        let log = answers.clone();
+        let (mut listening, url) = fake_api(move |method, params| {
+            log.lock().unwrap().push((method.to_owned(), params.clone()));
+            ok("true")
+        });
+
+        let mut bot = api_bot(&url);
+        bot.inline_query("^cats$", |bot, query, _, _| {
+            bot.answer_inline(&query.id)
+                .article("1", "Cats", "Meow")
+                .photo("2", "http://example.com/cat.jpg", "http://example.com/thumb.jpg")
+                .document("3", "Cat book", "http://example.com/cats.pdf", "application/pdf")
+                .cache_time(30)
+                .personal(true)
+                .next_offset("3")
+                .end()
+                .map(|_| ())
+        });
+        bot.handle_update(update(
+            r#"{"update_id": 1, "inline_query": {"id": "q1", "query": "cats", "offset": "",
+                "from": {"id": 2, "first_name": "A"}}}"#,
+        ));
+        listening.close().unwrap();
+
+        let answers = answers.lock().unwrap();
+        assert_eq!(answers.len(), 1);
+        let (ref method, ref params) = answers[0];
+        assert_eq!(method, "answerInlineQuery");
+        assert_eq!(params.find("inline_query_id"), Some(&"q1".to_json()));
+        assert_eq!(params.find("cache_time"), Some(&Json::U64(30)));
+        assert_eq!(params.find("is_personal"), Some(&Json::Boolean(true)));
+        assert_eq!(params.find("next_offset"), Some(&"3".to_json()));
+        assert_eq!(
+            params.find("results").unwrap().to_string(),
+            "[{\"id\":\"1\",\"input_message_content\":{\"message_text\":\"Meow\"},\
+             \"title\":\"Cats\",\"type\":\"article\"},\
+             {\"id\":\"2\",\"photo_url\":\"http://example.com/cat.jpg\",\
+             \"thumb_url\":\"http://example.com/thumb.jpg\",\"type\":\"photo\"},\
+             {\"document_url\":\"http://example.com/cats.pdf\",\"id\":\"3\",\
+             \"mime_type\":\"application/pdf\",\"title\":\"Cat book\",\"type\":\"document\"}]"
+        );
+    }
+
+    #[test]
+    fn unhandled_right() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut bot = test_bot();
+        let logger = |name: &'static str| {
+            let log = log.clone();
+            move |_: &AwesomeBot, _: &Message| log.lock().unwrap().push(name)
+        };
+        let start = log.clone();
+        bot.simple_command("start", move |_, _, _| start.lock().unwrap().push("start"));
+        bot.any_fn(logger("any"));
+        bot.unhandled_fn(logger("unhandled"));
+        bot.unhandled_fn(logger("unhandled 2"));
+
+        // The `any_fn` handlers run always, but they don't count as a match
+        bot.handle_update(text_update("/start"));
+        assert_eq!(*log.lock().unwrap(), vec!["start", "any"]);
+        log.lock().unwrap().clear();
+        bot.handle_update(text_update("Hello"));
+        assert_eq!(*log.lock().unwrap(), vec!["any", "unhandled", "unhandled 2"]);
+
+        // With the `First` policy, only the first unhandled handler runs
+        log.lock().unwrap().clear();
+        bot.dispatch_policy(DispatchPolicy::First);
+        bot.handle_update(text_update("Hello"));
+        assert_eq!(*log.lock().unwrap(), vec!["any", "unhandled"]);
+    }
+
+    #[test]
+    fn route_id_right() {
+        let mut bot = test_bot();
+        bot.simple_command("start", |_, _, _| {})
+            .photo_fn(|_, _, _| -> ::std::result::Result<(), String> { Ok(()) });
+        assert_eq!(
+            bot.muxers[0].id,
+            RouteId {
+                index: 0,
+                method: "simple_command",
+                pattern: Some(String::from("^/start(?:@usernamebot)?$")),
+            }
+        );
+        assert_eq!(bot.muxers[1].id.to_string(), "#1 photo_fn");
+    }
+
+    #[test]
+    fn invalid_route_right() {
+        let mut bot = test_bot();
+        let e = bot.try_command("echo (.+", |_, _, _, _| {}).err().unwrap();
+        assert_eq!(e.pattern, "^/echo(?:@usernamebot)? (.+$");
+        assert!(bot.try_regex("^[a-z]+$", |_, _, _, _| {}).is_ok());
+
+        // Ignored without strict mode, but kept
+        bot.simple_regex("(", |_, _, _| {});
+        assert_eq!(bot.muxers.len(), 1);
+        let errors = bot.route_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].pattern, "(");
+    }
+
+    #[test]
+    fn invalid_route_modifiers_right() {
+        let mut bot = test_bot();
+        bot.simple_regex("^[a-z]+$", |_, _, _| {});
+        // The modifiers of the ignored route don't change the valid one
+        bot.simple_regex("(", |_, _, _| {})
+            .priority(10)
+            .only_private()
+            .update_kinds(&[UpdateKind::EditedMessage]);
+        assert_eq!(bot.muxers.len(), 1);
+        assert_eq!(bot.muxers[0].priority, 0);
+        assert!(bot.muxers[0].filters.is_empty());
+        assert_eq!(bot.muxers[0].kinds, vec![UpdateKind::Message]);
+
+        // The next valid route has them again
+        bot.simple_regex("^[0-9]+$", |_, _, _| {}).priority(5);
+        assert_eq!(bot.muxers[1].priority, 5);
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_route_strict_right() {
+        let mut bot = test_bot();
+        bot.strict(true).simple_regex("(", |_, _, _| {});
+    }
 }
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+
+use rustc_serialize::json::{self, Json};
+use rustc_serialize::Decodable;
+use telegram_bot::{Integer, Message, User};
+
+/// An update received from Telegram, this replaces the `Update` of `telegram-bot`
+/// to support all the kinds of updates.
+#[derive(RustcDecodable, Debug, Clone)]
+pub struct Update {
+    pub update_id: Integer,
+    pub message: Option<Message>,
+    pub edited_message: Option<Message>,
+    /// Channel posts don't have a sender, but the `Message` of `telegram-bot` needs one,
+    /// so their `from` is made up from the channel: its id, its title as `first_name`
+    /// and its username. It isn't a real user.
+    pub channel_post: Option<Message>,
+    /// Like `channel_post`, its `from` is made up from the channel.
+    pub edited_channel_post: Option<Message>,
+    pub callback_query: Option<CallbackQuery>,
+    pub inline_query: Option<InlineQuery>,
+    pub chosen_inline_result: Option<ChosenInlineResult>,
+}
+
+/// An update that couldn't be decoded, given to the `on_invalid_update` hook. It's
+/// confirmed like the rest, so Telegram doesn't send it again.
+#[derive(Debug, Clone)]
+pub struct InvalidUpdate {
+    /// The `update_id` of the update, if it has one.
+    pub update_id: Option<Integer>,
+    /// The update as received.
+    pub json: Json,
+    /// Why it couldn't be decoded.
+    pub error: String,
+}
+
+// An update received, or why it couldn't be decoded
+pub(crate) type Received = Result<Update, InvalidUpdate>;
+
+// Decode an update, the ones that can't be decoded are returned with the error
+pub(crate) fn decode_update(mut j: Json) -> Received {
+    let id = j.find("update_id").and_then(|i| i.as_i64());
+    let invalid = |json, error| InvalidUpdate {
+        update_id: id,
+        json: json,
+        error: error,
+    };
+    if id.is_none() {
+        return Err(invalid(j, "missing `update_id`".to_owned()));
+    }
+
+    let original = j.clone();
+    if let Json::Object(ref mut obj) = j {
+        for key in &["channel_post", "edited_channel_post"] {
+            if let Some(&mut Json::Object(ref mut post)) = obj.get_mut(*key) {
+                fill_sender(post);
+            }
+        }
+    }
+
+    let mut decoder = json::Decoder::new(j);
+    Update::decode(&mut decoder).map_err(|e| invalid(original, e.to_string()))
+}
+
+// `Message` of `telegram-bot` always needs a sender, use the channel as the sender
+fn fill_sender(post: &mut json::Object) {
+    if post.contains_key("from") {
+        return;
+    }
+    let sender = match post.get("chat") {
+        Some(&Json::Object(ref chat)) => {
+            let mut user = BTreeMap::new();
+            user.insert(
+                "id".to_owned(),
+                chat.get("id").cloned().unwrap_or(Json::I64(0)),
+            );
+            user.insert(
+                "first_name".to_owned(),
+                chat.get("title")
+                    .cloned()
+                    .unwrap_or(Json::String(String::new())),
+            );
+            if let Some(username) = chat.get("username") {
+                user.insert("username".to_owned(), username.clone());
+            }
+            Json::Object(user)
+        }
+        _ => return,
+    };
+    post.insert("from".to_owned(), sender);
+}
+
+/// A query sent when a user presses a callback button of an inline keyboard.
+#[derive(RustcDecodable, Debug, Clone)]
+pub struct CallbackQuery {
+    /// Identifier of the query, use it to answer with `answer_callback`.
+    pub id: String,
+    /// The user that pressed the button.
+    pub from: User,
+    /// The message with the button, if it was sent by the bot.
+    pub message: Option<Message>,
+    /// Identifier of the message with the button, if it was sent in inline mode.
+    pub inline_message_id: Option<String>,
+    /// Data associated with the button.
+    pub data: Option<String>,
+}
+
+/// A query made to the bot in inline mode (`@yourbot query` in any chat).
+#[derive(RustcDecodable, Debug, Clone)]
+pub struct InlineQuery {
+    /// Identifier of the query, use it to answer with `answer_inline`.
+    pub id: String,
+    /// The user that made the query.
+    pub from: User,
+    /// Text of the query.
+    pub query: String,
+    /// Offset of the results to return, it's the `next_offset` of the previous answer.
+    pub offset: String,
+}
+
+/// A result of an inline query chosen by the user, you only receive them if you
+/// enable the inline feedback with @BotFather.
+#[derive(RustcDecodable, Debug, Clone)]
+pub struct ChosenInlineResult {
+    /// Identifier of the result chosen.
+    pub result_id: String,
+    /// The user that chose the result.
+    pub from: User,
+    /// Identifier of the message sent, only if it has an inline keyboard.
+    pub inline_message_id: Option<String>,
+    /// The query used to obtain the result.
+    pub query: String,
+}
+
+#[cfg(test)]
+mod test {
+    use super::decode_update;
+    use rustc_serialize::json::Json;
+
+    #[test]
+    fn decode_invalid_update_right() {
+        let invalid = decode_update(Json::from_str(r#"{"update_id": 3, "message": 1}"#).unwrap())
+            .unwrap_err();
+        assert_eq!(invalid.update_id, Some(3));
+        assert_eq!(invalid.json.find("message"), Some(&Json::U64(1)));
+        assert!(!invalid.error.is_empty());
+
+        let invalid = decode_update(Json::from_str(r#"{"message": 1}"#).unwrap()).unwrap_err();
+        assert_eq!(invalid.update_id, None);
+    }
+}
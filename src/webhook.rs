@@ -0,0 +1,242 @@
+use std::io::Read;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use hyper::buffer::BufReader;
+use hyper::header::{Connection, Headers};
+use hyper::method::Method;
+use hyper::net::{HttpStream, NetworkStream};
+use hyper::server::{Request, Response};
+use hyper::status::StatusCode;
+use hyper::uri::RequestUri;
+use rustc_serialize::json::Json;
+use telegram_bot::{Error, Result};
+use threadpool::ThreadPool;
+use update::{decode_update, Received};
+
+// Header that Telegram fills with the `secret_token` given in `setWebhook`
+const SECRET_HEADER: &'static str = "X-Telegram-Bot-Api-Secret-Token";
+
+// Biggest body accepted, the updates are much smaller
+const MAX_BODY: u64 = 1024 * 1024;
+
+// Requests answered at the same time
+const CONNECTION_THREADS: usize = 8;
+
+/// HTTP server of the webhook, started with `listen`.
+///
+/// It runs until it's closed, then it answers the requests being received with
+/// `503 Service Unavailable`, so Telegram sends those updates again later, and frees
+/// its address.
+pub struct WebhookServer {
+    /// The address where the server listens.
+    pub socket: SocketAddr,
+    endpoint: Arc<Endpoint>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl WebhookServer {
+    /// Stop the server. Once it returns, no more updates are sent and the address is free.
+    pub fn close(&mut self) {
+        *self.endpoint.tx.lock().unwrap() = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for WebhookServer {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+// What the requests are checked against, and where the updates go
+struct Endpoint {
+    path: String,
+    secret: Option<String>,
+    // `None` once the server is closed
+    tx: Mutex<Option<Sender<Received>>>,
+}
+
+/// Start an HTTP server in `addr` that decodes the updates POSTed by Telegram to `path`
+/// and sends them through `tx`. The server keeps running until the returned
+/// `WebhookServer` is closed.
+pub fn listen(
+    addr: &str,
+    path: &str,
+    secret: Option<&str>,
+    tx: Sender<Received>,
+) -> Result<WebhookServer> {
+    let listener = try!(TcpListener::bind(addr).map_err(Error::Io));
+    let socket = try!(listener.local_addr().map_err(Error::Io));
+    // Without blocking, to notice when it's closed
+    try!(listener.set_nonblocking(true).map_err(Error::Io));
+
+    let endpoint = Arc::new(Endpoint {
+        path: path.to_owned(),
+        secret: secret.map(|s| s.to_owned()),
+        tx: Mutex::new(Some(tx)),
+    });
+    let thread = {
+        let endpoint = endpoint.clone();
+        thread::spawn(move || accept(listener, endpoint))
+    };
+    Ok(WebhookServer {
+        socket: socket,
+        endpoint: endpoint,
+        thread: Some(thread),
+    })
+}
+
+// Accept connections until the server is closed, the listener is dropped then
+fn accept(listener: TcpListener, endpoint: Arc<Endpoint>) {
+    let pool = ThreadPool::new(CONNECTION_THREADS);
+    while endpoint.tx.lock().unwrap().is_some() {
+        match listener.accept() {
+            Ok((stream, addr)) => {
+                let endpoint = endpoint.clone();
+                pool.execute(move || endpoint.serve(stream, addr));
+            }
+            // Nothing to accept, or an error like too many open files, wait a bit
+            Err(_) => thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}
+
+impl Endpoint {
+    // Answer the request of one connection
+    fn serve(&self, stream: TcpStream, addr: SocketAddr) {
+        // Some systems give the connections without blocking, like the listener
+        let _ = stream.set_nonblocking(false);
+        let _ = stream.set_read_timeout(Some(Duration::from_secs(10)));
+        let _ = stream.set_write_timeout(Some(Duration::from_secs(10)));
+        let mut reader = match stream.try_clone() {
+            Ok(s) => HttpStream(s),
+            Err(_) => return,
+        };
+        let mut writer = stream;
+
+        let status = {
+            let mut rdr = BufReader::new(&mut reader as &mut NetworkStream);
+            match Request::new(&mut rdr, addr) {
+                Ok(mut req) => self.receive_update(&mut req),
+                Err(_) => StatusCode::BadRequest,
+            }
+        };
+        let mut headers = Headers::new();
+        headers.set(Connection::close());
+        let mut res = Response::new(&mut writer, &mut headers);
+        *res.status_mut() = status;
+        let _ = res.send(b"");
+    }
+
+    // Validates one request and, if it carries an update, sends it to the bot.
+    // Returns the status code to answer with.
+    fn receive_update(&self, req: &mut Request) -> StatusCode {
+        if req.method != Method::Post {
+            return StatusCode::MethodNotAllowed;
+        }
+
+        match req.uri {
+            // The query string doesn't matter
+            RequestUri::AbsolutePath(ref p) if p.split('?').next() == Some(&self.path[..]) => {}
+            _ => return StatusCode::NotFound,
+        }
+
+        if let Some(ref s) = self.secret {
+            let given = req.headers.get_raw(SECRET_HEADER).and_then(|v| v.first());
+            if !given.map_or(false, |v| same_secret(v, s.as_bytes())) {
+                return StatusCode::Forbidden;
+            }
+        }
+
+        // Read one more byte than allowed to know if the body is too big
+        let mut body = String::new();
+        if req.take(MAX_BODY + 1).read_to_string(&mut body).is_err() {
+            return StatusCode::BadRequest;
+        }
+        if body.len() as u64 > MAX_BODY {
+            return StatusCode::PayloadTooLarge;
+        }
+
+        // The updates that can't be decoded are accepted too, so Telegram doesn't send
+        // them again, and reported by the bot
+        let json = match Json::from_str(&body) {
+            Ok(json) => json,
+            Err(_) => return StatusCode::BadRequest,
+        };
+        match *self.tx.lock().unwrap() {
+            Some(ref tx) if tx.send(decode_update(json)).is_ok() => StatusCode::Ok,
+            // The bot is stopping, Telegram sends it again later
+            _ => StatusCode::ServiceUnavailable,
+        }
+    }
+}
+
+// Compare the secrets taking the same time wherever they differ, so the time
+// of the answer doesn't tell how much of the secret is right
+fn same_secret(given: &[u8], secret: &[u8]) -> bool {
+    given.len() == secret.len()
+        && given
+            .iter()
+            .zip(secret)
+            .fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::{listen, same_secret, MAX_BODY};
+    use hyper::status::StatusCode;
+    use hyper::Client;
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use test::fixtures::{post, text_update_json};
+
+    #[test]
+    fn listen_right() {
+        let (tx, rx) = mpsc::channel();
+        let mut server = listen("127.0.0.1:0", "/hook", Some("secret"), tx).unwrap();
+        let url = format!("http://{}", server.socket);
+        let update = text_update_json(7, "Hi");
+
+        assert_eq!(post(&format!("{}/other", url), Some("secret"), &update), StatusCode::NotFound);
+        assert_eq!(post(&format!("{}/hook", url), None, &update), StatusCode::Forbidden);
+        assert_eq!(post(&format!("{}/hook", url), Some("wrong"), &update), StatusCode::Forbidden);
+        assert_eq!(post(&format!("{}/hook", url), Some("secret"), "{"), StatusCode::BadRequest);
+        let get = Client::new().get(&format!("{}/hook", url)).send().unwrap();
+        assert_eq!(get.status, StatusCode::MethodNotAllowed);
+        let big = format!("\"{}\"", "a".repeat(MAX_BODY as usize));
+        assert_eq!(post(&format!("{}/hook", url), Some("secret"), &big), StatusCode::PayloadTooLarge);
+        assert!(rx.try_recv().is_err());
+
+        // The query string is ignored
+        let hook = format!("{}/hook?token=1", url);
+        assert_eq!(post(&hook, Some("secret"), &update), StatusCode::Ok);
+        assert_eq!(rx.recv().unwrap().unwrap().update_id, 7);
+        // The updates that can't be decoded are accepted and given to the bot
+        let invalid = r#"{"update_id": 8, "message": 1}"#;
+        assert_eq!(post(&hook, Some("secret"), invalid), StatusCode::Ok);
+        assert_eq!(rx.recv().unwrap().unwrap_err().update_id, Some(8));
+
+        // Nobody receives them, so Telegram has to send them again
+        drop(rx);
+        assert_eq!(post(&hook, Some("secret"), &update), StatusCode::ServiceUnavailable);
+
+        // Once closed, the address is free
+        let socket = server.socket;
+        server.close();
+        assert!(TcpListener::bind(socket).is_ok());
+    }
+
+    #[test]
+    fn same_secret_right() {
+        assert!(same_secret(b"secret", b"secret"));
+        assert!(!same_secret(b"secreT", b"secret"));
+        assert!(!same_secret(b"secret1", b"secret"));
+        assert!(!same_secret(b"", b"secret"));
+    }
+}